@@ -1,4 +1,7 @@
-use crate::rttui::channel::ChannelConfig;
+use crate::rttui::{
+    channel::ChannelConfig, keys::KeyBindings, layout::SplitLayout, scrollback::ScrollbackLimit,
+    theme::ThemeConfig, timestamp::TimestampConfig,
+};
 use anyhow::bail;
 use figment::{
     providers::{Format, Json, Toml, Yaml},
@@ -25,6 +28,50 @@ pub struct Config {
     pub probe: Probe,
     pub rtt: Rtt,
     pub gdb: Gdb,
+    pub semihosting: Semihosting,
+    pub swo: Swo,
+    pub faults: Faults,
+}
+
+impl Config {
+    /// Checks the values which can be parsed, but don't make sense.
+    fn validate(&self) -> anyhow::Result<()> {
+        if let Some(port) = self.swo.ports.iter().find(|&&port| port >= 32) {
+            bail!(
+                "The ITM has stimulus ports 0 to 31, but port {} is captured",
+                port
+            );
+        }
+        for (i, channel) in self.rtt.channels.iter().enumerate() {
+            if let Some(plot) = &channel.plot {
+                if !(plot.window.is_finite() && plot.window > 0.0) {
+                    bail!(
+                        "The plot window of RTT channel {} is {}, but must be a positive number of seconds",
+                        i,
+                        plot.window
+                    );
+                }
+            }
+        }
+        let cores = &self.rtt.cores;
+        if let Some(core) = cores
+            .iter()
+            .enumerate()
+            .find_map(|(i, core)| cores[..i].contains(core).then_some(core))
+        {
+            bail!("Core {} is listed twice in the RTT cores", core);
+        }
+        if let ControlBlock::Range { start, end } = self.rtt.control_block {
+            if start >= end {
+                bail!(
+                    "The RTT control block range {:#010x} to {:#010x} is empty",
+                    start,
+                    end
+                );
+            }
+        }
+        Ok(())
+    }
 }
 
 /// The probe config struct holding all the possible probe options.
@@ -51,6 +98,17 @@ pub struct Flashing {
     pub restore_unwritten_bytes: bool,
     pub flash_layout_output_path: Option<String>,
     pub do_chip_erase: bool,
+    /// The images flashed for the cores of multi-core chips.
+    pub images: Vec<CoreImage>,
+}
+
+/// The firmware of a core.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CoreImage {
+    pub core: usize,
+    /// The ELF file, relative to the manifest path.
+    pub path: PathBuf,
 }
 
 /// The reset config struct holding all the possible reset options.
@@ -59,6 +117,32 @@ pub struct Flashing {
 pub struct Reset {
     pub enabled: bool,
     pub halt_afterwards: bool,
+    /// The core which is reset.
+    pub core: usize,
+    /// The steps to reset the cores of multi-core chips, used instead of resetting `core`.
+    pub sequence: Vec<ResetStep>,
+}
+
+/// A step of a reset sequence.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResetStep {
+    pub core: usize,
+    pub action: ResetAction,
+    /// How long to wait after the step, in ms.
+    pub delay: Option<u64>,
+}
+
+/// What a step of a reset sequence does to its core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ResetAction {
+    /// Resets the core and lets it run.
+    Reset,
+    /// Resets the core and halts it before the first instruction.
+    ResetAndHalt,
+    Halt,
+    /// Resumes a halted core, e.g. to release it after the others were set up.
+    Run,
 }
 
 /// The general config struct holding all the possible general options.
@@ -84,12 +168,83 @@ pub struct Rtt {
     pub channels: Vec<ChannelConfig>,
     /// Connection timeout in ms.
     pub timeout: usize,
+    /// Where the RTT control block is searched for.
+    pub control_block: ControlBlock,
+    /// The cores whose RTT control blocks are attached.
+    pub cores: Vec<usize>,
     /// Whether to show timestamps in RTTUI
     pub show_timestamps: bool,
+    /// How lines are timestamped, unless configured for the channel.
+    pub timestamps: TimestampConfig,
     /// Whether to save rtt history buffer on exit to file named history.txt
     pub log_enabled: bool,
     /// Where to save rtt history buffer relative to manifest path.
     pub log_path: PathBuf,
+    /// How several channels are shown at the same time.
+    pub split: SplitLayout,
+    /// Indices of the tabs shown when the screen is split.
+    pub split_tabs: Vec<usize>,
+    /// Whether to add a tab showing the messages of all channels in receive order.
+    pub all_tab: bool,
+    /// Whether to keep the commands sent to down channels between sessions.
+    pub input_history: bool,
+    /// Where to keep the command history of each down channel, relative to the manifest directory.
+    pub input_history_path: PathBuf,
+    /// Whether to capture the mouse for scrolling, switching tabs and selecting text.
+    pub mouse: bool,
+    /// Whether to annotate the addresses of functions in text and defmt lines with their symbols.
+    pub symbolize_addresses: bool,
+    /// How much history is kept per channel, unless configured for the channel.
+    pub scrollback: ScrollbackLimit,
+    /// The keys bound to the actions of the RTT UI.
+    pub keys: KeyBindings,
+    /// The colors of the RTT UI.
+    pub theme: ThemeConfig,
+}
+
+/// Where the RTT control block is searched for.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ControlBlock {
+    /// At the address of this symbol of the ELF file, or in all of RAM if it has no such symbol.
+    Symbol(String),
+    /// At exactly this address.
+    Address(u32),
+    /// Anywhere from `start` up to `end`.
+    Range { start: u32, end: u32 },
+}
+
+/// The semihosting config struct holding all the possible semihosting options.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Semihosting {
+    pub enabled: bool,
+    /// The directory the target may open files in, relative to the manifest path.
+    pub host_directory: Option<PathBuf>,
+}
+
+/// The swo config struct holding all the possible swo options.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Swo {
+    pub enabled: bool,
+    /// The frequency of the trace clock in Hz, usually the core clock.
+    pub clock: Option<u32>,
+    /// The baud rate of the SWO pin.
+    pub baud_rate: u32,
+    /// The ITM stimulus ports which are captured, 0 to 31.
+    pub ports: Vec<u8>,
+    /// Whether the program counter is sampled periodically.
+    pub pc_sampling: bool,
+    /// Whether exceptions being entered, exited and returned to are traced.
+    pub exception_trace: bool,
+}
+
+/// The faults config struct holding all the possible fault detection options.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Faults {
+    pub enabled: bool,
 }
 
 /// The gdb config struct holding all the possible gdb options.
@@ -97,7 +252,19 @@ pub struct Rtt {
 #[serde(deny_unknown_fields)]
 pub struct Gdb {
     pub enabled: bool,
+    /// Where the GDB server listens, `host:port` or `unix:` followed by the path of a socket.
     pub gdb_connection_string: Option<String>,
+    /// A GDB server for each of these cores, instead of one at `gdb_connection_string`.
+    pub cores: Vec<GdbCore>,
+}
+
+/// The GDB server of a core.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct GdbCore {
+    pub core: usize,
+    /// Where the GDB server listens, like `gdb_connection_string`.
+    pub connection_string: String,
 }
 
 impl Configs {
@@ -181,10 +348,19 @@ impl Configs {
                         defined_profiles.join(", ")
                     );
                 }
+                config.validate()?;
                 Ok(config)
             }
         }
     }
+    /// The configuration of default.toml alone, independent of the files around the tests.
+    #[cfg(test)]
+    pub fn defaults() -> Configs {
+        Configs {
+            figment: Figment::new().merge(Toml::string(include_str!("default.toml")).nested()),
+        }
+    }
+
     #[cfg(test)]
     pub fn new_with_test_data(conf_dir: PathBuf) -> Configs {
         let mut cfs = Configs::new(conf_dir);
@@ -203,7 +379,11 @@ impl Configs {
 
 #[cfg(test)]
 mod test {
-    use super::Configs;
+    use super::{Configs, ControlBlock};
+    use figment::{
+        providers::{Format, Toml},
+        Figment,
+    };
 
     #[test]
     fn default_profile() {
@@ -223,4 +403,61 @@ mod test {
         let configs = Configs::new_with_test_data(std::env::current_dir().unwrap());
         let _superfluous: anyhow::Error = configs.select_defined("default").unwrap_err();
     }
+
+    /// Selects the default profile with `toml` merged into it.
+    fn select(toml: &str) -> anyhow::Result<super::Config> {
+        let mut configs = Configs::new(std::env::current_dir().unwrap());
+        configs.figment = configs.figment.merge(Toml::string(toml).nested());
+        configs.select_defined("default")
+    }
+
+    #[test]
+    fn invalid_values() {
+        assert!(select("[default.swo]\nports = [0, 31]").is_ok());
+        assert!(select("[default.swo]\nports = [0, 32]").is_err());
+        assert!(select("[default.rtt]\ncores = [0, 1]").is_ok());
+        let plot = |window| {
+            format!(
+                "[default.rtt]\nchannels = [{{ up = 0, format = \"BinaryLE\", plot = {{ samples = \"U8\", window = {} }} }}]",
+                window
+            )
+        };
+        assert!(select(&plot("2.5")).is_ok());
+        assert!(select(&plot("0.0")).is_err());
+        assert!(select(&plot("-1.0")).is_err());
+        assert!(select(&plot("nan")).is_err());
+        assert!(select("[default.rtt]\ncores = [0, 1, 0]").is_err());
+        assert!(
+            select("[default.rtt]\ncontrol_block = { start = 0x20000000, end = 0x20001000 }")
+                .is_ok()
+        );
+        assert!(
+            select("[default.rtt]\ncontrol_block = { start = 0x20001000, end = 0x20001000 }")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn control_block_locations() {
+        let parse = |toml| {
+            Figment::from(Toml::string(toml))
+                .extract_inner::<ControlBlock>("control_block")
+                .unwrap()
+        };
+        assert_eq!(
+            parse(r#"control_block = "_SEGGER_RTT""#),
+            ControlBlock::Symbol("_SEGGER_RTT".to_owned())
+        );
+        assert_eq!(
+            parse("control_block = 0x20000400"),
+            ControlBlock::Address(0x2000_0400)
+        );
+        assert_eq!(
+            parse("control_block = { start = 0x20000000, end = 0x20001000 }"),
+            ControlBlock::Range {
+                start: 0x2000_0000,
+                end: 0x2000_1000
+            }
+        );
+    }
 }
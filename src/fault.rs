@@ -0,0 +1,480 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use gimli::{
+    BaseAddresses, CfaRule, DebugFrame, LittleEndian, Register, RegisterRule, UnwindContext,
+    UnwindSection,
+};
+use probe_rs::{Architecture, Core, CoreStatus, CoreType, HaltReason, MemoryInterface, RegisterId};
+
+use crate::symbols::Symbols;
+
+const CFSR: u64 = 0xe000_ed28;
+const HFSR: u64 = 0xe000_ed2c;
+const MMFAR: u64 = 0xe000_ed34;
+const BFAR: u64 = 0xe000_ed38;
+const DEMCR: u64 = 0xe000_edfc;
+/// Halts the core when it enters the HardFault handler.
+const DEMCR_VC_HARDERR: u32 = 1 << 10;
+
+const CFSR_MMARVALID: u32 = 1 << 7;
+const CFSR_BFARVALID: u32 = 1 << 15;
+
+/// The register holding the process stack pointer, when read through the debug interface.
+const PSP: u16 = 18;
+const SP: usize = 13;
+const LR: usize = 14;
+const PC: usize = 15;
+
+/// How often the core is checked for a crash.
+const CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The most frames shown in a backtrace, in case the stack is corrupted.
+const MAX_FRAMES: usize = 32;
+
+/// The functions a core halted in has crashed.
+const CRASH_FUNCTIONS: &[&str] = &[
+    "HardFault",
+    "HardFault_",
+    "HardFaultTrampoline",
+    "rust_begin_unwind",
+];
+
+/// The fault status bits of the CFSR, with their descriptions.
+const CFSR_BITS: &[(u32, &str)] = &[
+    (1 << 0, "instruction access violation"),
+    (1 << 1, "data access violation"),
+    (1 << 3, "memory management fault on unstacking"),
+    (1 << 4, "memory management fault on stacking"),
+    (
+        1 << 5,
+        "memory management fault during lazy floating point state preservation",
+    ),
+    (1 << 8, "instruction bus error"),
+    (1 << 9, "precise data bus error"),
+    (1 << 10, "imprecise data bus error"),
+    (1 << 11, "bus fault on unstacking"),
+    (1 << 12, "bus fault on stacking"),
+    (
+        1 << 13,
+        "bus fault during lazy floating point state preservation",
+    ),
+    (1 << 16, "undefined instruction"),
+    (1 << 17, "invalid state, e.g. a branch to an ARM address"),
+    (1 << 18, "invalid exception return"),
+    (1 << 19, "no coprocessor, e.g. the FPU is disabled"),
+    (1 << 20, "stack overflow"),
+    (1 << 24, "unaligned access"),
+    (1 << 25, "division by zero"),
+];
+
+/// The fault status bits of the HFSR, with their descriptions.
+const HFSR_BITS: &[(u32, &str)] = &[
+    (1 << 1, "bus fault on a vector table read"),
+    (1 << 30, "forced, escalated from a configurable fault"),
+    (1 << 31, "debug event"),
+];
+
+/// A crash of the firmware, ready to be shown.
+#[derive(Debug, Clone)]
+pub struct Crash {
+    pub title: String,
+    pub lines: Vec<String>,
+}
+
+/// A frame of a backtrace.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Frame {
+    pc: u32,
+    /// Whether the frame was interrupted by an exception.
+    exception: bool,
+}
+
+/// The result of unwinding the stack.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Unwound {
+    frames: Vec<Frame>,
+    /// The registers stacked on the first exception: r0-r3, r12, lr, pc and xPSR.
+    stacked: Option<[u32; 8]>,
+}
+
+/// Checks whether the core crashed, and describes the crash when it did.
+#[derive(Debug)]
+pub struct FaultMonitor {
+    symbols: Arc<Symbols>,
+    last_check: Instant,
+    /// Whether the current halt was already reported.
+    reported: bool,
+}
+
+impl FaultMonitor {
+    pub fn new(symbols: Arc<Symbols>) -> Self {
+        Self {
+            symbols,
+            last_check: Instant::now(),
+            reported: false,
+        }
+    }
+
+    /// Makes the core halt when it crashes: on HardFaults and, with `panic_breakpoint`, in the
+    /// panic handler.
+    ///
+    /// The breakpoint on the panic handler takes one of the hardware breakpoints, which a GDB
+    /// server doesn't know about, so it is only set without one.
+    ///
+    /// Only ARM cores are supported, others are left alone.
+    pub fn setup(&self, core: &mut Core, panic_breakpoint: bool) -> Result<()> {
+        if core.architecture() != Architecture::Arm {
+            log::warn!("Fault detection is only supported on ARM targets.");
+            return Ok(());
+        }
+        let demcr = core.read_word_32(DEMCR)?;
+        core.write_word_32(DEMCR, demcr | DEMCR_VC_HARDERR)?;
+        if let Some(address) = self.symbols.address_of("rust_begin_unwind") {
+            if panic_breakpoint {
+                core.set_hw_breakpoint(address.into())?;
+            } else {
+                log::info!(
+                    "No breakpoint is set on the panic handler while the GDB server runs, \
+                     panics are only detected when the firmware halts in it."
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the crash the core is halted on, once per halt.
+    pub fn check(&mut self, core: &mut Core) -> Result<Option<Crash>> {
+        if core.architecture() != Architecture::Arm || self.last_check.elapsed() < CHECK_INTERVAL {
+            return Ok(None);
+        }
+        self.last_check = Instant::now();
+
+        let status = core.status()?;
+        let crashed = match status {
+            CoreStatus::LockedUp | CoreStatus::Halted(HaltReason::Exception) => true,
+            CoreStatus::Halted(_) => {
+                let pc = core.read_core_reg(core.registers().program_counter())?;
+                self.symbols
+                    .function(pc)
+                    .is_some_and(|(name, _)| CRASH_FUNCTIONS.contains(&name))
+            }
+            _ => false,
+        };
+        if !crashed {
+            self.reported = false;
+            return Ok(None);
+        }
+        if self.reported {
+            return Ok(None);
+        }
+        self.reported = true;
+
+        if status == CoreStatus::LockedUp {
+            core.halt(Duration::from_millis(100))?;
+        }
+        self.describe(core, status).map(Some)
+    }
+
+    fn describe(&self, core: &mut Core, status: CoreStatus) -> Result<Crash> {
+        let mut registers = [0; 16];
+        for (i, register) in registers.iter_mut().enumerate() {
+            *register = core.read_core_reg(RegisterId::from(i as u16))?;
+        }
+        let psp = core.read_core_reg(RegisterId::from(PSP))?;
+        let pc = registers[PC];
+
+        let title = match self.symbols.function(pc) {
+            _ if status == CoreStatus::LockedUp => "The core locked up".to_owned(),
+            Some(("rust_begin_unwind", _)) => "The firmware panicked".to_owned(),
+            Some((name, _)) if name.starts_with("HardFault") => "HardFault".to_owned(),
+            _ => "The core halted on an exception".to_owned(),
+        };
+        let mut lines = vec![format!("Halted at {}", self.symbols.describe(pc))];
+
+        if core.core_type() != CoreType::Armv6m {
+            let cfsr = core.read_word_32(CFSR)?;
+            let hfsr = core.read_word_32(HFSR)?;
+            if cfsr != 0 {
+                lines.push(format!(
+                    "CFSR {:#010x}: {}",
+                    cfsr,
+                    describe_bits(cfsr, CFSR_BITS)
+                ));
+            }
+            if hfsr != 0 {
+                lines.push(format!(
+                    "HFSR {:#010x}: {}",
+                    hfsr,
+                    describe_bits(hfsr, HFSR_BITS)
+                ));
+            }
+            if cfsr & CFSR_MMARVALID != 0 {
+                lines.push(format!("MMFAR {:#010x}", core.read_word_32(MMFAR)?));
+            }
+            if cfsr & CFSR_BFARVALID != 0 {
+                lines.push(format!("BFAR {:#010x}", core.read_word_32(BFAR)?));
+            }
+        }
+
+        let unwound = self.unwind(registers, psp, &mut |address| {
+            Ok(core.read_word_32(address.into())?)
+        });
+        if let Some(stacked) = unwound.stacked {
+            let names = ["r0", "r1", "r2", "r3", "r12", "lr", "pc", "xpsr"];
+            lines.push("Stacked registers:".to_owned());
+            lines.push(
+                names
+                    .iter()
+                    .zip(stacked.iter())
+                    .map(|(name, value)| format!("{} {:#010x}", name, value))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            );
+        }
+        lines.push("Backtrace:".to_owned());
+        for (i, frame) in unwound.frames.iter().enumerate() {
+            lines.push(format!(
+                "{:>4}: {:#010x} {}{}",
+                i,
+                frame.pc,
+                self.symbols.describe(frame.pc),
+                if frame.exception { " <exception>" } else { "" }
+            ));
+        }
+
+        Ok(Crash { title, lines })
+    }
+
+    /// Unwinds the stack with the call frame information of the firmware, starting at
+    /// `registers`. Stops at the first function without unwind information.
+    fn unwind(
+        &self,
+        mut registers: [u32; 16],
+        psp: u32,
+        read_word: &mut dyn FnMut(u32) -> Result<u32>,
+    ) -> Unwound {
+        let mut unwound = Unwound::default();
+        let mut exception = false;
+
+        while unwound.frames.len() < MAX_FRAMES {
+            let pc = registers[PC];
+            unwound.frames.push(Frame { pc, exception });
+            exception = false;
+
+            // Without unwind information, only an exception handler which has not pushed
+            // anything yet can be unwound.
+            let (cfa, saved) = match self.unwind_info(pc, &registers) {
+                Some(info) => info,
+                None if is_exc_return(registers[LR]) => (registers[SP], Vec::new()),
+                None => break,
+            };
+
+            let mut caller = registers;
+            for (register, address) in saved {
+                match read_word(address) {
+                    Ok(value) => caller[register] = value,
+                    Err(_) => return unwound,
+                }
+            }
+            caller[SP] = cfa;
+            let return_address = caller[LR];
+
+            if is_exc_return(return_address) {
+                // The registers of the interrupted code are stacked on the stack it used.
+                let sp = if return_address & 0x4 != 0 { psp } else { cfa };
+                let mut stacked = [0; 8];
+                for (i, value) in stacked.iter_mut().enumerate() {
+                    match read_word(sp + 4 * i as u32) {
+                        Ok(word) => *value = word,
+                        Err(_) => return unwound,
+                    }
+                }
+                caller[..4].copy_from_slice(&stacked[..4]);
+                caller[12] = stacked[4];
+                caller[LR] = stacked[5];
+                caller[PC] = stacked[6];
+                // The extended frame includes the floating point registers, and the stack may
+                // have been aligned to 8 bytes.
+                let mut size = if return_address & 0x10 == 0 {
+                    0x68
+                } else {
+                    0x20
+                };
+                if stacked[7] & (1 << 9) != 0 {
+                    size += 4;
+                }
+                caller[SP] = sp + size;
+                unwound.stacked.get_or_insert(stacked);
+                exception = true;
+            } else {
+                caller[PC] = return_address & !1;
+            }
+
+            let done =
+                caller[PC] == 0 || (caller[PC] == registers[PC] && caller[SP] == registers[SP]);
+            registers = caller;
+            if done {
+                break;
+            }
+        }
+
+        unwound
+    }
+
+    /// Returns the canonical frame address of the function at `pc` and the addresses of the
+    /// registers it saved.
+    fn unwind_info(&self, pc: u32, registers: &[u32; 16]) -> Option<(u32, Vec<(usize, u32)>)> {
+        let mut debug_frame = DebugFrame::new(self.symbols.debug_frame()?, LittleEndian);
+        debug_frame.set_address_size(4);
+        let mut context = UnwindContext::new();
+        let row = debug_frame
+            .unwind_info_for_address(
+                &BaseAddresses::default(),
+                &mut context,
+                pc.into(),
+                DebugFrame::cie_from_offset,
+            )
+            .ok()?;
+
+        let cfa = match row.cfa() {
+            CfaRule::RegisterAndOffset { register, offset } => {
+                let base = *registers.get(register.0 as usize)?;
+                (i64::from(base) + offset) as u32
+            }
+            CfaRule::Expression(_) => return None,
+        };
+        let saved = (0..16)
+            .filter_map(|register| match row.register(Register(register)) {
+                RegisterRule::Offset(offset) => {
+                    Some((register as usize, (i64::from(cfa) + offset) as u32))
+                }
+                _ => None,
+            })
+            .collect();
+        Some((cfa, saved))
+    }
+}
+
+/// Whether `address` is one of the special values returning from an exception.
+fn is_exc_return(address: u32) -> bool {
+    address & 0xff00_0000 == 0xff00_0000 && address != 0xffff_ffff
+}
+
+fn describe_bits(value: u32, bits: &[(u32, &str)]) -> String {
+    let descriptions = bits
+        .iter()
+        .filter(|(bit, _)| value & bit != 0)
+        .map(|(_, description)| *description)
+        .collect::<Vec<_>>();
+    if descriptions.is_empty() {
+        "no known fault".to_owned()
+    } else {
+        descriptions.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, sync::Arc};
+
+    use super::{describe_bits, FaultMonitor, Frame, CFSR_BITS};
+    use crate::symbols::Symbols;
+
+    #[test]
+    fn fault_status() {
+        assert_eq!(
+            describe_bits(0x0200_8200, CFSR_BITS),
+            "precise data bus error, division by zero"
+        );
+        assert_eq!(describe_bits(0x8000, CFSR_BITS), "no known fault");
+    }
+
+    #[test]
+    fn unwind_with_debug_frame() {
+        // A CIE for Thumb code: code alignment 2, data alignment -4, the return address in lr
+        // and the CFA in sp.
+        let mut debug_frame = vec![12, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 1, 0, 2, 0x7c, 14];
+        debug_frame.extend_from_slice(&[0x0c, 13, 0]);
+        // An FDE for a function at 0x08000400, which starts with `push {r7, lr}`: the CFA is
+        // sp + 8 after the first instruction, lr is saved at CFA - 4 and r7 at CFA - 8.
+        debug_frame.extend_from_slice(&[20, 0, 0, 0, 0, 0, 0, 0]);
+        debug_frame.extend_from_slice(&0x0800_0400u32.to_le_bytes());
+        debug_frame.extend_from_slice(&0x40u32.to_le_bytes());
+        debug_frame.extend_from_slice(&[0x41, 0x0e, 8, 0x8e, 1, 0x87, 2, 0]);
+        let monitor = FaultMonitor::new(Arc::new(Symbols::with_debug_frame(debug_frame)));
+
+        let mut registers = [0; 16];
+        registers[7] = 0x2000_0ff8;
+        registers[13] = 0x2000_0ff8;
+        registers[14] = 0x0800_0411;
+        registers[15] = 0x0800_0410;
+        let memory = [(0x2000_0ff8, 0x2000_1010), (0x2000_0ffc, 0x0800_0233)]
+            .iter()
+            .copied()
+            .collect::<HashMap<u32, u32>>();
+
+        let unwound = monitor.unwind(registers, 0, &mut |address| {
+            memory
+                .get(&address)
+                .copied()
+                .ok_or_else(|| anyhow::anyhow!("unmapped"))
+        });
+        assert_eq!(unwound.stacked, None);
+        // The caller is found with the saved lr, and has no unwind information itself.
+        assert_eq!(
+            unwound.frames,
+            [
+                Frame {
+                    pc: 0x0800_0410,
+                    exception: false
+                },
+                Frame {
+                    pc: 0x0800_0232,
+                    exception: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unwind_exception_frame() {
+        let monitor = FaultMonitor::new(Arc::new(Symbols::default()));
+        // Halted on entry of the HardFault handler, the exception frame is on the main stack.
+        let mut registers = [0; 16];
+        registers[13] = 0x2000_0100;
+        registers[14] = 0xffff_fff9;
+        registers[15] = 0x0800_0400;
+        let stacked = [1, 2, 3, 4, 12, 0x0800_0301, 0x0800_0210, 0x0100_0000];
+        let memory = stacked
+            .iter()
+            .enumerate()
+            .map(|(i, word)| (0x2000_0100 + 4 * i as u32, *word))
+            .collect::<HashMap<_, _>>();
+
+        let unwound = monitor.unwind(registers, 0, &mut |address| {
+            memory
+                .get(&address)
+                .copied()
+                .ok_or_else(|| anyhow::anyhow!("unmapped"))
+        });
+        assert_eq!(unwound.stacked, Some(stacked));
+        // Without unwind information, the backtrace ends in the interrupted function.
+        assert_eq!(
+            unwound.frames,
+            [
+                Frame {
+                    pc: 0x0800_0400,
+                    exception: false
+                },
+                Frame {
+                    pc: 0x0800_0210,
+                    exception: true
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use std::{
+    convert::TryFrom,
+    fmt,
+    net::{SocketAddr, TcpListener, ToSocketAddrs},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use probe_rs::Session;
+use probe_rs_gdb_server::GdbInstanceConfiguration;
+
+use crate::config;
+
+/// Where a GDB server accepts connections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    Tcp(SocketAddr),
+    /// A Unix domain socket, forwarded to the server on a local TCP port.
+    Unix(PathBuf),
+}
+
+impl Endpoint {
+    /// Parses a connection string, either `host:port` or `unix:` followed by the path of a socket.
+    pub fn parse(connection_string: &str) -> Result<Self> {
+        if let Some(path) = connection_string.strip_prefix("unix:") {
+            return Ok(Endpoint::Unix(PathBuf::from(path)));
+        }
+        connection_string
+            .to_socket_addrs()
+            .with_context(|| format!("Invalid GDB connection string {}", connection_string))?
+            .next()
+            .map(Endpoint::Tcp)
+            .ok_or_else(|| anyhow!("{} does not resolve to an address", connection_string))
+    }
+
+    /// The endpoint of the `i`-th type of core: the following port, or the path followed by
+    /// `.i`.
+    fn nth(&self, i: usize) -> Result<Self> {
+        match self {
+            Endpoint::Tcp(address) => {
+                let port = u16::try_from(i)
+                    .ok()
+                    .and_then(|i| address.port().checked_add(i))
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "No port after {} is left for the GDB server of core type {}",
+                            address,
+                            i
+                        )
+                    })?;
+                Ok(Endpoint::Tcp(SocketAddr::new(address.ip(), port)))
+            }
+            Endpoint::Unix(path) if i > 0 => Ok(Endpoint::Unix(PathBuf::from(format!(
+                "{}.{}",
+                path.display(),
+                i
+            )))),
+            Endpoint::Unix(path) => Ok(Endpoint::Unix(path.clone())),
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Tcp(address) => write!(f, "{}", address),
+            Endpoint::Unix(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// A GDB server and where it accepts connections.
+pub struct Server {
+    pub instance: GdbInstanceConfiguration,
+    pub endpoint: Endpoint,
+}
+
+/// Returns the GDB servers configured in `config`, one per core listed in `gdb.cores`, or else
+/// one per type of core at `gdb.gdb_connection_string`.
+pub fn servers(session: &Session, config: &config::Gdb) -> Result<Vec<Server>> {
+    let connection_string = config
+        .gdb_connection_string
+        .as_deref()
+        .unwrap_or("127.0.0.1:1337");
+    // The instances are created for a placeholder address, the ports are assigned below.
+    let instances = GdbInstanceConfiguration::from_session(session, Some("127.0.0.1:0"));
+
+    if config.cores.is_empty() {
+        let endpoint = Endpoint::parse(connection_string)?;
+        return instances
+            .into_iter()
+            .enumerate()
+            .map(|(i, instance)| server(instance, endpoint.nth(i)?))
+            .collect();
+    }
+
+    config
+        .cores
+        .iter()
+        .map(|core| {
+            let instance = instances
+                .iter()
+                .find(|instance| instance.cores.contains(&core.core))
+                .ok_or_else(|| anyhow!("The target has no core {} to debug", core.core))?;
+            let instance = GdbInstanceConfiguration {
+                core_type: instance.core_type,
+                cores: vec![core.core],
+                socket_addrs: Vec::new(),
+            };
+            server(instance, Endpoint::parse(&core.connection_string)?)
+        })
+        .collect()
+}
+
+/// Binds `instance` to `endpoint`, forwarding Unix domain sockets to a free local port.
+fn server(mut instance: GdbInstanceConfiguration, endpoint: Endpoint) -> Result<Server> {
+    let address = match &endpoint {
+        Endpoint::Tcp(address) => *address,
+        Endpoint::Unix(path) => {
+            let address = TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+            forward_unix_socket(path, address)?;
+            address
+        }
+    };
+    instance.socket_addrs = vec![address];
+    Ok(Server { instance, endpoint })
+}
+
+/// Accepts connections on the Unix domain socket at `path` and forwards them to `address`.
+#[cfg(unix)]
+fn forward_unix_socket(path: &Path, address: SocketAddr) -> Result<()> {
+    use std::{
+        io,
+        net::{Shutdown, TcpStream},
+        os::unix::{fs::FileTypeExt, net::UnixListener},
+        thread,
+    };
+
+    // A socket left over from an earlier session can't be bound again, but anything else at the
+    // path is kept.
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(anyhow!(
+                "Can't bind the GDB socket {}, the path exists and is not a socket",
+                path.display()
+            ));
+        }
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove the stale socket {}", path.display()))?;
+    }
+    let listener = UnixListener::bind(path)
+        .with_context(|| format!("Failed to bind the GDB socket {}", path.display()))?;
+    thread::spawn(move || {
+        for client in listener.incoming() {
+            let forward = client.and_then(|mut client| {
+                let mut server = TcpStream::connect(address)?;
+                let mut client_reader = client.try_clone()?;
+                let mut server_writer = server.try_clone()?;
+                thread::spawn(move || {
+                    let _ = io::copy(&mut client_reader, &mut server_writer);
+                    let _ = server_writer.shutdown(Shutdown::Write);
+                });
+                io::copy(&mut server, &mut client)?;
+                client.shutdown(Shutdown::Both)
+            });
+            if let Err(e) = forward {
+                log::warn!("GDB connection over the Unix domain socket failed: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn forward_unix_socket(path: &Path, _address: SocketAddr) -> Result<()> {
+    Err(anyhow!(
+        "The GDB server can't listen on the Unix domain socket {}, Unix domain sockets are only \
+         supported on Unix, use host:port instead",
+        path.display()
+    ))
+}
+
+/// Returns the command which starts GDB for `elf` and connects it to `endpoint`.
+pub fn gdb_command(endpoint: &Endpoint, elf: &Path) -> String {
+    format!(
+        "gdb -ex \"target extended-remote {}\" {}",
+        endpoint,
+        shell_quote(&elf.display().to_string())
+    )
+}
+
+/// Quotes `text` as a single argument of a POSIX shell.
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{gdb_command, Endpoint};
+
+    #[test]
+    fn endpoints() {
+        let tcp = Endpoint::parse("127.0.0.1:1338").unwrap();
+        assert_eq!(tcp, Endpoint::Tcp("127.0.0.1:1338".parse().unwrap()));
+        let unix = Endpoint::parse("unix:/tmp/embed-gdb").unwrap();
+        assert_eq!(unix, Endpoint::Unix("/tmp/embed-gdb".into()));
+        assert!(Endpoint::parse("1337").is_err());
+
+        // Further types of cores are served on the following ports or sockets.
+        assert_eq!(
+            tcp.nth(1).unwrap(),
+            Endpoint::Tcp("127.0.0.1:1339".parse().unwrap())
+        );
+        assert!(Endpoint::parse("127.0.0.1:65535").unwrap().nth(1).is_err());
+        assert_eq!(unix.nth(0).unwrap(), unix);
+        assert_eq!(
+            unix.nth(2).unwrap(),
+            Endpoint::Unix("/tmp/embed-gdb.2".into())
+        );
+
+        let elf = Path::new("target/thumbv7em-none-eabihf/debug/app");
+        assert_eq!(
+            gdb_command(&tcp, elf),
+            "gdb -ex \"target extended-remote 127.0.0.1:1338\" 'target/thumbv7em-none-eabihf/debug/app'"
+        );
+        let elf = Path::new("my firmware/it's here");
+        assert_eq!(
+            gdb_command(&tcp, elf),
+            "gdb -ex \"target extended-remote 127.0.0.1:1338\" 'my firmware/it'\\''s here'"
+        );
+        assert_eq!(
+            gdb_command(&unix, Path::new("app")),
+            "gdb -ex \"target extended-remote /tmp/embed-gdb\" 'app'"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_socket_forwarding() {
+        use std::{
+            io::{Read, Write},
+            net::{Shutdown, TcpListener},
+            os::unix::net::{UnixListener, UnixStream},
+        };
+
+        use super::forward_unix_socket;
+
+        // A server which echoes the first connection.
+        let server = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = server.accept().unwrap();
+            let mut data = Vec::new();
+            stream.read_to_end(&mut data).unwrap();
+            stream.write_all(&data).unwrap();
+        });
+
+        let path = std::env::temp_dir().join(format!("cargo-embed-gdb-{}", std::process::id()));
+        // Files which aren't sockets are not replaced.
+        std::fs::write(&path, b"").unwrap();
+        assert!(forward_unix_socket(&path, address).is_err());
+        std::fs::remove_file(&path).unwrap();
+
+        // A socket left behind by an earlier session is.
+        drop(UnixListener::bind(&path).unwrap());
+        forward_unix_socket(&path, address).unwrap();
+
+        let mut client = UnixStream::connect(&path).unwrap();
+        client.write_all(b"$qSupported#37").unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).unwrap();
+        assert_eq!(response, b"$qSupported#37");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,6 +1,11 @@
 mod config;
 mod error;
+mod fault;
+mod gdb;
 mod rttui;
+mod semihosting;
+mod swo;
+mod symbols;
 
 include!(concat!(env!("OUT_DIR"), "/meta.rs"));
 
@@ -9,11 +14,10 @@ use chrono::Local;
 use colored::*;
 use std::{
     env, fs,
-    fs::File,
     io::Write,
     panic,
-    path::Path,
-    process,
+    path::{Path, PathBuf},
+    process, ptr,
     sync::{Arc, Mutex},
     time::{Duration, Instant},
 };
@@ -21,7 +25,7 @@ use std::{
 use probe_rs::{
     config::TargetSelector,
     flashing::{download_file_with_options, DownloadOptions, FlashProgress, Format, ProgressEvent},
-    DebugProbeSelector, Permissions, Probe,
+    DebugProbeSelector, Permissions, Probe, Session,
 };
 #[cfg(feature = "sentry")]
 use probe_rs_cli_util::logging::{ask_to_log_crash, capture_anyhow, capture_panic};
@@ -36,9 +40,18 @@ use probe_rs_cli_util::{
 
 use probe_rs_rtt::{Rtt, ScanRegion};
 
-use probe_rs_gdb_server::GdbInstanceConfiguration;
-
-use crate::rttui::channel::DataFormat;
+use crate::{
+    fault::{Crash, FaultMonitor},
+    rttui::{
+        channel::{ChannelState, DataFormat, DefmtState},
+        recording::Recording,
+        source::MemoryChannel,
+        status::SessionInfo,
+    },
+    semihosting::{Event, Semihosting},
+    swo::{PrefixedLines, Swo},
+    symbols::Symbols,
+};
 
 lazy_static::lazy_static! {
     static ref METADATA: Arc<Mutex<Metadata>> = Arc::new(Mutex::new(Metadata {
@@ -76,6 +89,36 @@ struct Opt {
     cargo_options: CargoOptions,
 }
 
+#[derive(Debug, clap::Parser)]
+#[clap(
+    name = "cargo embed replay",
+    about = "Replays an RTT session recorded while logging was enabled, without a probe."
+)]
+struct ReplayOpt {
+    #[clap(
+        name = "recording",
+        help = "The recording, saved next to the RTT logs."
+    )]
+    recording: PathBuf,
+    #[clap(
+        long = "elf",
+        help = "The firmware, to decode defmt channels and symbolize addresses."
+    )]
+    elf: Option<PathBuf>,
+    #[clap(
+        long = "speed",
+        default_value = "1",
+        help = "How many times as fast as it was recorded the data is replayed, 0 replays everything at once."
+    )]
+    speed: f64,
+    #[clap(
+        long = "config",
+        default_value = "default",
+        help = "The config whose RTT options are used."
+    )]
+    config: String,
+}
+
 fn main() {
     let next = panic::take_hook();
     panic::set_hook(Box::new(move |info| {
@@ -141,6 +184,11 @@ fn main_try() -> Result<()> {
 
     let mut args: Vec<_> = args.collect();
 
+    // `cargo embed replay` shows a recording instead of connecting to a target.
+    if args.get(1).map(String::as_str) == Some("replay") {
+        return replay(&args[1..]);
+    }
+
     // Get commandline options.
     let opt = Opt::parse_from(&args);
 
@@ -149,10 +197,13 @@ fn main_try() -> Result<()> {
     // Get the config.
     let config_name = opt.config.as_deref().unwrap_or("default");
     let configs = config::Configs::new(work_dir.clone());
-    let config = configs.select_defined(config_name)?;
+    let mut config = configs.select_defined(config_name)?;
 
     logging::init(Some(config.general.log_level));
 
+    // The input history belongs to the project, not to the directory cargo-embed was run from.
+    config.rtt.input_history_path = manifest_dir(&work_dir).join(&config.rtt.input_history_path);
+
     // Make sure we load the config given in the cli parameters.
     for cdp in &config.general.chip_descriptions {
         probe_rs::config::add_target_from_yaml(Path::new(cdp))
@@ -193,6 +244,17 @@ fn main_try() -> Result<()> {
         )
     })?;
 
+    // The images of the cores, the artifact runs on core 0 unless another image is configured.
+    let mut images = config
+        .flashing
+        .images
+        .iter()
+        .map(|image| (image.core, work_dir.join(&image.path)))
+        .collect::<Vec<_>>();
+    if !images.iter().any(|(core, _)| *core == 0) {
+        images.insert(0, (0, path.to_owned()));
+    }
+
     logging::println(format!("      {} {}", "Config".green().bold(), config_name));
     logging::println(format!(
         "      {} {}",
@@ -272,6 +334,8 @@ fn main_try() -> Result<()> {
 
     log::info!("Protocol speed {} kHz", protocol_speed);
 
+    let probe_name = probe.get_name();
+
     let permissions = if config.flashing.enabled || config.gdb.enabled {
         Permissions::new().allow_erase_all()
     } else {
@@ -301,131 +365,23 @@ fn main_try() -> Result<()> {
         // Start timer.
         let instant = Instant::now();
 
-        if !opt.disable_progressbars {
-            // Create progress bars.
-            let multi_progress = MultiProgress::new();
-            let style = ProgressStyle::default_bar()
-                .tick_chars("⠁⠁⠉⠙⠚⠒⠂⠂⠒⠲⠴⠤⠄⠄⠤⠠⠠⠤⠦⠖⠒⠐⠐⠒⠓⠋⠉⠈⠈✔")
-                .progress_chars("##-")
-                .template("{msg:.green.bold} {spinner} [{elapsed_precise}] [{wide_bar}] {bytes:>8}/{total_bytes:>8} @ {bytes_per_sec:>10} (eta {eta:3})")?;
-
-            // Create a new progress bar for the fill progress if filling is enabled.
-            let fill_progress = if config.flashing.restore_unwritten_bytes {
-                let fill_progress = Arc::new(multi_progress.add(ProgressBar::new(0)));
-                fill_progress.set_style(style.clone());
-                fill_progress.set_message("     Reading flash  ");
-                Some(fill_progress)
-            } else {
-                None
-            };
-
-            // Create a new progress bar for the erase progress.
-            let erase_progress = Arc::new(multi_progress.add(ProgressBar::new(0)));
-            {
-                logging::set_progress_bar(erase_progress.clone());
+        // The chip is erased only before the first image, not to erase the others again.
+        for (i, (core, image)) in images.iter().enumerate() {
+            if images.len() > 1 {
+                logging::println(format!(
+                    "    {} {} for core {}",
+                    "Flashing".green().bold(),
+                    image.display(),
+                    core
+                ));
             }
-            erase_progress.set_style(style.clone());
-            erase_progress.set_message("     Erasing sectors");
-
-            // Create a new progress bar for the program progress.
-            let program_progress = multi_progress.add(ProgressBar::new(0));
-            program_progress.set_style(style);
-            program_progress.set_message(" Programming pages  ");
-
-            let flash_layout_output_path = config.flashing.flash_layout_output_path.clone();
-            // Register callback to update the progress.
-            let progress = FlashProgress::new(move |event| {
-                use ProgressEvent::*;
-                match event {
-                    Initialized { flash_layout } => {
-                        let total_page_size: u32 =
-                            flash_layout.pages().iter().map(|s| s.size()).sum();
-                        let total_sector_size: u64 =
-                            flash_layout.sectors().iter().map(|s| s.size()).sum();
-                        let total_fill_size: u64 =
-                            flash_layout.fills().iter().map(|s| s.size()).sum();
-                        if let Some(fp) = fill_progress.as_ref() {
-                            fp.set_length(total_fill_size)
-                        }
-                        erase_progress.set_length(total_sector_size);
-                        program_progress.set_length(total_page_size as u64);
-                        let visualizer = flash_layout.visualize();
-                        flash_layout_output_path
-                            .as_ref()
-                            .map(|path| visualizer.write_svg(path));
-                    }
-                    StartedProgramming => {
-                        program_progress.enable_steady_tick(Duration::from_millis(100));
-                        program_progress.reset_elapsed();
-                    }
-                    StartedErasing => {
-                        erase_progress.enable_steady_tick(Duration::from_millis(100));
-                        erase_progress.reset_elapsed();
-                    }
-                    StartedFilling => {
-                        if let Some(fp) = fill_progress.as_ref() {
-                            fp.enable_steady_tick(Duration::from_millis(100))
-                        };
-                        if let Some(fp) = fill_progress.as_ref() {
-                            fp.reset_elapsed()
-                        };
-                    }
-                    PageProgrammed { size, .. } => {
-                        program_progress.inc(size as u64);
-                    }
-                    SectorErased { size, .. } => {
-                        erase_progress.inc(size);
-                    }
-                    PageFilled { size, .. } => {
-                        if let Some(fp) = fill_progress.as_ref() {
-                            fp.inc(size)
-                        };
-                    }
-                    FailedErasing => {
-                        erase_progress.abandon();
-                        program_progress.abandon();
-                    }
-                    FinishedErasing => {
-                        erase_progress.finish();
-                    }
-                    FailedProgramming => {
-                        program_progress.abandon();
-                    }
-                    FinishedProgramming => {
-                        program_progress.finish();
-                    }
-                    FailedFilling => {
-                        if let Some(fp) = fill_progress.as_ref() {
-                            fp.abandon()
-                        };
-                    }
-                    FinishedFilling => {
-                        if let Some(fp) = fill_progress.as_ref() {
-                            fp.finish()
-                        };
-                    }
-                }
-            });
-
-            let mut options = DownloadOptions::new();
-
-            options.progress = Some(&progress);
-            options.keep_unwritten_bytes = config.flashing.restore_unwritten_bytes;
-            options.do_chip_erase = config.flashing.do_chip_erase;
-
-            download_file_with_options(&mut session, path, Format::Elf, options)
-                .with_context(|| format!("failed to flash {}", path.display()))?;
-
-            // If we don't do this, the inactive progress bars will swallow log
-            // messages, so they'll never be printed anywhere.
-            logging::clear_progress_bar();
-        } else {
-            let mut options = DownloadOptions::new();
-            options.keep_unwritten_bytes = config.flashing.restore_unwritten_bytes;
-            options.do_chip_erase = config.flashing.do_chip_erase;
-
-            download_file_with_options(&mut session, path, Format::Elf, options)
-                .with_context(|| format!("failed to flash {}", path.display()))?;
+            flash_image(
+                &mut session,
+                image,
+                &config.flashing,
+                !opt.disable_progressbars,
+                config.flashing.do_chip_erase && i == 0,
+            )?;
         }
 
         // Stop timer.
@@ -437,8 +393,10 @@ fn main_try() -> Result<()> {
         ));
     }
 
-    if config.reset.enabled {
-        let mut core = session.core(0)?;
+    if config.reset.enabled && !config.reset.sequence.is_empty() {
+        run_reset_sequence(&mut session, &config.reset.sequence)?;
+    } else if config.reset.enabled {
+        let mut core = session.core(config.reset.core)?;
         let halt_timeout = Duration::from_millis(500);
         #[allow(deprecated)] // Remove in 0.10
         if config.flashing.halt_afterwards {
@@ -454,24 +412,110 @@ fn main_try() -> Result<()> {
         }
     }
 
+    let session_info = SessionInfo {
+        chip: session.target().name.clone(),
+        probe: probe_name,
+        protocol: config.probe.protocol.to_string(),
+        speed_khz: protocol_speed,
+    };
+
+    let mut swo = if config.swo.enabled {
+        Some(Swo::setup(&mut session, &config.swo).context("failed to set up SWO")?)
+    } else {
+        None
+    };
+
+    // The ELF files are read once, for the symbols, the defmt table and the RTT control block.
+    let elfs = images
+        .iter()
+        .map(|(core, image)| {
+            fs::read(image)
+                .map(|elf| (*core, elf))
+                .with_context(|| format!("failed to read {}", image.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // The symbols of each image.
+    let symbolize = config.faults.enabled || config.rtt.enabled && config.rtt.symbolize_addresses;
+    let symbols = elfs
+        .iter()
+        .map(|(n, elf)| {
+            let symbols = if symbolize {
+                Symbols::parse(elf).unwrap_or_else(|e| {
+                    log::warn!("Addresses of core {} are not symbolized: {:?}", n, e);
+                    Symbols::default()
+                })
+            } else {
+                Symbols::default()
+            };
+            (*n, Arc::new(symbols))
+        })
+        .collect::<Vec<_>>();
+
+    // The cores running an image are watched for crashes.
+    let mut faults = Vec::new();
+    if config.faults.enabled {
+        for (n, _) in &images {
+            let monitor = FaultMonitor::new(core_image(&symbols, *n).clone());
+            monitor
+                .setup(&mut session.core(*n)?, !config.gdb.enabled)
+                .with_context(|| format!("failed to set up fault detection on core {}", n))?;
+            faults.push((*n, monitor));
+        }
+    }
+
+    // The requests of the cores running an image are serviced, if they are Cortex-M cores.
+    let mut semihosting = Vec::new();
+    if config.semihosting.enabled {
+        let host_directory = config
+            .semihosting
+            .host_directory
+            .as_ref()
+            .map(|directory| work_dir.join(directory));
+        let core_types = session.list_cores();
+        for (n, _) in &images {
+            match core_types.iter().find(|(core, _)| core == n) {
+                Some((_, core_type)) if !Semihosting::supports(*core_type) => log::warn!(
+                    "Semihosting is not supported on core {} ({:?}), only on Cortex-M cores.",
+                    n,
+                    core_type
+                ),
+                _ => semihosting.push((*n, Semihosting::new(host_directory.clone()))),
+            }
+        }
+    }
+
     let session = Arc::new(Mutex::new(session));
 
     let mut gdb_thread_handle = None;
     if config.gdb.enabled {
-        let gdb_connection_string = config.gdb.gdb_connection_string.clone();
-        let session = session.clone();
-        gdb_thread_handle = Some(std::thread::spawn(move || {
-            let gdb_connection_string = gdb_connection_string.as_deref().or(Some("127.0.0.1:1337"));
-            // This next unwrap will always resolve as the connection string is always Some(T).
+        let servers = gdb::servers(&session.lock().unwrap(), &config.gdb)
+            .context("failed to set up the GDB server")?;
+        for server in &servers {
+            let cores = &server.instance.cores;
             logging::println(format!(
-                "    {} listening at {}",
+                "    {} listening at {} (core {})",
                 "GDB stub".green().bold(),
-                gdb_connection_string.as_ref().unwrap(),
+                server.endpoint,
+                cores
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", "),
             ));
-            let instances = {
-                let session = session.lock().unwrap();
-                GdbInstanceConfiguration::from_session(&session, gdb_connection_string)
-            };
+            let elf = core_image(&images, cores.first().copied().unwrap_or_default());
+            logging::println(format!(
+                "             {}",
+                gdb::gdb_command(&server.endpoint, elf)
+            ));
+        }
+
+        let session = session.clone();
+        gdb_thread_handle = Some(std::thread::spawn(move || {
+            let instances = servers
+                .into_iter()
+                .map(|server| server.instance)
+                .collect::<Vec<_>>();
             if let Err(e) = probe_rs_gdb_server::run(&session, instances.iter()) {
                 logging::eprintln("During the execution of GDB an error was encountered:");
                 logging::eprintln(format!("{:?}", e));
@@ -484,143 +528,266 @@ fn main_try() -> Result<()> {
             .channels
             .iter()
             .any(|elem| elem.format == DataFormat::Defmt);
-        let defmt_state = if defmt_enable {
-            let elf = fs::read(path).unwrap();
-            if let Some(table) = defmt_decoder::Table::parse(&elf)? {
-                let locs = {
-                    let locs = table.get_locations(&elf)?;
-
-                    if !table.is_empty() && locs.is_empty() {
-                        log::warn!("Insufficient DWARF info; compile your program with `debug = 2` to enable location info.");
-                        None
-                    } else if table.indices().all(|idx| locs.contains_key(&(idx as u64))) {
-                        Some(locs)
-                    } else {
-                        log::warn!(
-                            "Location info is incomplete; it will be omitted from the output."
-                        );
-                        None
-                    }
+        // The defmt table of each image the channels of a core are decoded with.
+        let defmt_states = elfs
+            .iter()
+            .map(|(n, elf)| {
+                let used = config
+                    .rtt
+                    .cores
+                    .iter()
+                    .any(|&core| ptr::eq(core_image(&elfs, core), elf));
+                let state = if defmt_enable && used {
+                    load_defmt(elf)?
+                } else {
+                    None
                 };
-                Some((table, locs))
-            } else {
-                log::error!("Defmt enabled in rtt channel config, but defmt table couldn't be loaded from binary.");
-                None
-            }
-        } else {
-            None
-        };
+                Ok((*n, state))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        // Each core's control block is searched for with the symbols of its own image.
+        let rtt_header_addresses = config
+            .rtt
+            .cores
+            .iter()
+            .map(|&n| rtt_scan_region(&config.rtt.control_block, core_image(&elfs, n).as_slice()))
+            .collect::<Vec<_>>();
 
         let t = std::time::Instant::now();
         let mut error = None;
 
         let mut i = 1;
+        // The control blocks of cores attached in earlier attempts are kept.
+        let mut rtts = Vec::new();
 
         while (t.elapsed().as_millis() as usize) < config.rtt.timeout {
             log::info!("Initializing RTT (attempt {})...", i);
             i += 1;
 
-            let rtt_header_address = if let Ok(mut file) = File::open(path) {
-                if let Some(address) = rttui::app::App::get_rtt_symbol(&mut file) {
-                    ScanRegion::Exact(address as u32)
-                } else {
-                    ScanRegion::Ram
-                }
-            } else {
-                ScanRegion::Ram
-            };
-
             let mut session_handle = session.lock().unwrap();
             let memory_map = session_handle.target().memory_map.clone();
-            let mut core = session_handle.core(0)?;
-
-            match Rtt::attach_region(&mut core, &memory_map, &rtt_header_address) {
-                Ok(mut rtt) => {
-                    // RTT supports three different "modes" for channels, which
-                    // describe how the firmware should handle writes that won't
-                    // fit in the available buffer.  The config file can
-                    // optionally specify a mode to use for all up channels,
-                    // and/or a mode for specific channels.
-                    let default_up_mode = config.rtt.up_mode;
-
-                    for up_channel in rtt.up_channels().iter() {
-                        let mut specific_mode = None;
-                        for channel_config in config
-                            .rtt
-                            .channels
-                            .iter()
-                            .filter(|ch_conf| ch_conf.up == Some(up_channel.number()))
-                        {
-                            if let Some(mode) = channel_config.up_mode {
-                                if specific_mode.is_some()
-                                    && specific_mode != channel_config.up_mode
-                                {
-                                    // Can't safely resolve this generally...
-                                    return Err(anyhow!("Conflicting modes specified for RTT up channel {}: {:?} and {:?}",
-                                        up_channel.number(), specific_mode.unwrap(), mode));
+            for (&n, rtt_header_address) in config.rtt.cores.iter().zip(&rtt_header_addresses) {
+                if rtts.iter().any(|(core, _)| *core == n) {
+                    continue;
+                }
+                let mut core = session_handle
+                    .core(n)
+                    .with_context(|| format!("failed to attach to core {}", n))?;
+
+                match Rtt::attach_region(&mut core, &memory_map, rtt_header_address) {
+                    Ok(mut rtt) => {
+                        // RTT supports three different "modes" for channels, which
+                        // describe how the firmware should handle writes that won't
+                        // fit in the available buffer.  The config file can
+                        // optionally specify a mode to use for all up channels,
+                        // and/or a mode for specific channels.
+                        let default_up_mode = config.rtt.up_mode;
+
+                        for up_channel in rtt.up_channels().iter() {
+                            let mut specific_mode = None;
+                            for channel_config in config.rtt.channels.iter().filter(|ch_conf| {
+                                ch_conf.up == Some(up_channel.number())
+                                    && ch_conf.core.unwrap_or(n) == n
+                            }) {
+                                if let Some(mode) = channel_config.up_mode {
+                                    if specific_mode.is_some()
+                                        && specific_mode != channel_config.up_mode
+                                    {
+                                        // Can't safely resolve this generally...
+                                        return Err(anyhow!("Conflicting modes specified for RTT up channel {}: {:?} and {:?}",
+                                            up_channel.number(), specific_mode.unwrap(), mode));
+                                    }
+
+                                    specific_mode = Some(mode);
                                 }
+                            }
 
-                                specific_mode = Some(mode);
+                            if let Some(mode) = specific_mode.or(default_up_mode) {
+                                // Only set the mode when the config file says to,
+                                // when not set explicitly, the firmware picks.
+                                log::debug!(
+                                    "Setting RTT channel {} to {:?}",
+                                    up_channel.number(),
+                                    &mode
+                                );
+                                up_channel.set_mode(&mut core, mode)?;
                             }
                         }
 
-                        if let Some(mode) = specific_mode.or(default_up_mode) {
-                            // Only set the mode when the config file says to,
-                            // when not set explicitly, the firmware picks.
-                            log::debug!(
-                                "Setting RTT channel {} to {:?}",
-                                up_channel.number(),
-                                &mode
-                            );
-                            up_channel.set_mode(&mut core, mode)?;
-                        }
+                        rtts.push((n, rtt));
                     }
+                    Err(err) => {
+                        error = Some(anyhow!("Error attaching to RTT on core {}: {}", n, err));
+                    }
+                };
+            }
+            drop(session_handle);
 
-                    drop(core);
-                    drop(session_handle);
-                    log::info!("RTT initialized.");
-
-                    // `App` puts the terminal into a special state, as required
-                    // by the text-based UI. If a panic happens while the
-                    // terminal is in that state, this will completely mess up
-                    // the user's terminal (misformatted panic message, newlines
-                    // being ignored, input characters not being echoed, ...).
-                    //
-                    // The following panic hook cleans up the terminal, while
-                    // otherwise preserving the behavior of the default panic
-                    // hook (or whichever custom hook might have been registered
-                    // before).
-                    let previous_panic_hook = panic::take_hook();
-                    panic::set_hook(Box::new(move |panic_info| {
-                        rttui::app::clean_up_terminal();
-                        previous_panic_hook(panic_info);
-                    }));
-
-                    let chip_name = config.general.chip.as_deref().unwrap_or_default();
-                    let logname =
-                        format!("{}_{}_{}", name, chip_name, Local::now().timestamp_millis());
-                    let mut app = rttui::app::App::new(rtt, &config, logname)?;
-                    loop {
-                        let mut session_handle = session.lock().unwrap();
-                        let mut core = session_handle.core(0)?;
-                        app.poll_rtt(&mut core);
-                        app.render(&defmt_state);
-                        if app.handle_event(&mut core) {
-                            logging::println("Shutting down.");
-                            return Ok(());
-                        };
-                        std::thread::sleep(Duration::from_millis(10));
+            if rtts.len() < config.rtt.cores.len() {
+                log::debug!("Failed to initialize RTT. Retrying until timeout.");
+                continue;
+            }
+            log::info!("RTT initialized.");
+
+            set_terminal_panic_hook();
+
+            let chip_name = config.general.chip.as_deref().unwrap_or_default();
+            let logname = format!("{}_{}_{}", name, chip_name, Local::now().timestamp_millis());
+            // The output of semihosting and of each SWO stream is shown in a tab of
+            // its own.
+            let semihosting_outputs = semihosting
+                .iter()
+                .map(|(n, _)| {
+                    let name = if semihosting.len() > 1 {
+                        format!("Core {}: Semihosting", n)
+                    } else {
+                        "Semihosting".to_owned()
+                    };
+                    MemoryChannel::new(Some(name), 0)
+                })
+                .collect::<Vec<_>>();
+            let swo_outputs = if swo.is_some() {
+                Swo::streams(&config.swo)
+                    .into_iter()
+                    .map(|stream| (stream, MemoryChannel::new(Some(stream.name()), 0)))
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            let mut other_tabs = Vec::new();
+            for output in &semihosting_outputs {
+                other_tabs.push(output_tab(output, &config));
+            }
+            for (_, output) in &swo_outputs {
+                other_tabs.push(output_tab(output, &config));
+            }
+
+            let mut app =
+                rttui::app::App::new(rtts, other_tabs, &config, logname, session_info.clone())?;
+            // The tabs of semihosting and SWO belong to core 0.
+            let mut tab_cores = config.rtt.cores.clone();
+            if !tab_cores.contains(&0) {
+                tab_cores.push(0);
+            }
+            for &n in &tab_cores {
+                if let Some(defmt_state) = *core_image(&defmt_states, n) {
+                    app.set_defmt(n, defmt_state);
+                }
+                if config.rtt.symbolize_addresses {
+                    app.set_symbols(n, core_image(&symbols, n).clone());
+                }
+            }
+            loop {
+                let mut session_handle = session.lock().unwrap();
+                if let Some(swo) = swo.as_mut() {
+                    let streams = match swo.poll(&mut session_handle) {
+                        Ok(streams) => streams,
+                        Err(e) => {
+                            app.quit();
+                            return Err(e.context("failed to read SWO"));
+                        }
+                    };
+                    for (stream, data) in streams {
+                        if let Some((_, output)) = swo_outputs.iter().find(|(s, _)| *s == stream) {
+                            output.push(&data);
+                        }
+                    }
+                }
+                match poll_semihosting(&mut session_handle, &mut semihosting) {
+                    Ok(events) => {
+                        for (i, event) in events {
+                            match event {
+                                Event::Output(data) => semihosting_outputs[i].push(&data),
+                                Event::Exit(code) => {
+                                    app.quit();
+                                    exit_with_target_code(code);
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        app.quit();
+                        return Err(e);
                     }
                 }
-                Err(err) => {
-                    error = Some(anyhow!("Error attaching to RTT: {}", err));
+                match check_faults(&mut session_handle, &mut faults) {
+                    Ok(Some(crash)) => app.show_crash(crash),
+                    Ok(None) => {}
+                    Err(e) => {
+                        app.quit();
+                        return Err(e);
+                    }
                 }
-            };
-
-            log::debug!("Failed to initialize RTT. Retrying until timeout.");
+                app.poll_rtt(Some(&mut session_handle));
+                app.render();
+                if app.handle_event(Some(&mut session_handle)) {
+                    logging::println("Shutting down.");
+                    return Ok(());
+                };
+                std::thread::sleep(Duration::from_millis(10));
+            }
         }
-        if let Some(error) = error {
-            return Err(error);
+        let missing = config
+            .rtt
+            .cores
+            .iter()
+            .filter(|&&n| !rtts.iter().any(|(core, _)| *core == n))
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        let error = error.unwrap_or_else(|| anyhow!("The timeout passed before attaching"));
+        return Err(error.context(format!(
+            "failed to attach RTT on core {}",
+            missing.join(", ")
+        )));
+    }
+
+    // Without RTT, the output of semihosting and SWO and crashes are printed until the target
+    // exits.
+    if !semihosting.is_empty() || swo.is_some() || !faults.is_empty() {
+        logging::println(format!(
+            "     {} printing the output of the target",
+            "Running".green().bold()
+        ));
+        let mut stdout = std::io::stdout();
+        // The lines of the SWO streams are told apart by the name of their stream.
+        let mut swo_lines = PrefixedLines::default();
+        loop {
+            let mut session_handle = session.lock().unwrap();
+            let mut output = Vec::new();
+            if let Some(swo) = swo.as_mut() {
+                output.extend(
+                    swo.poll(&mut session_handle)?
+                        .into_iter()
+                        .map(|(stream, data)| swo_lines.push(stream, &data)),
+                );
+                output.push(swo_lines.flush_stale(Instant::now()));
+            }
+            let mut exit = None;
+            for (_, event) in poll_semihosting(&mut session_handle, &mut semihosting)? {
+                match event {
+                    Event::Output(data) => output.push(data),
+                    Event::Exit(code) => exit = Some(code),
+                }
+            }
+            let crash = check_faults(&mut session_handle, &mut faults)?;
+            drop(session_handle);
+
+            for data in output {
+                stdout.write_all(&data)?;
+            }
+            stdout.flush()?;
+            if let Some(code) = exit {
+                exit_with_target_code(code);
+            }
+            if let Some(crash) = crash {
+                logging::eprintln(format!("       {} {}", "Crash".red().bold(), crash.title));
+                for line in &crash.lines {
+                    logging::eprintln(format!("             {}", line));
+                }
+                process::exit(1);
+            }
+            std::thread::sleep(Duration::from_millis(10));
         }
     }
 
@@ -637,6 +804,392 @@ fn main_try() -> Result<()> {
     Ok(())
 }
 
+/// Returns the directory of the Cargo manifest cargo builds in `work_dir`, or `work_dir` itself
+/// if there is none.
+fn manifest_dir(work_dir: &Path) -> PathBuf {
+    work_dir
+        .ancestors()
+        .find(|dir| dir.join("Cargo.toml").is_file())
+        .unwrap_or(work_dir)
+        .to_owned()
+}
+
+/// Replays a recorded RTT session in the RTT UI, without a probe.
+fn replay(args: &[String]) -> Result<()> {
+    let opt = ReplayOpt::parse_from(args);
+
+    let work_dir = std::env::current_dir()?;
+    let configs = config::Configs::new(work_dir);
+    let config = configs.select_defined(&opt.config)?;
+
+    logging::init(Some(config.general.log_level));
+
+    let recording = Recording::load(&opt.recording)?;
+    let elf = match &opt.elf {
+        Some(path) => {
+            Some(fs::read(path).with_context(|| format!("failed to read {}", path.display()))?)
+        }
+        None => None,
+    };
+    let defmt_state = if recording
+        .channels
+        .iter()
+        .any(|channel| channel.format == DataFormat::Defmt)
+    {
+        match &elf {
+            Some(elf) => load_defmt(elf)?,
+            None => {
+                return Err(anyhow!(
+                    "The recording contains defmt channels, use --elf to select the firmware for decoding them."
+                ))
+            }
+        }
+    } else {
+        None
+    };
+
+    let name = opt
+        .recording
+        .file_stem()
+        .and_then(|f| f.to_str())
+        .unwrap_or("recording");
+    let logname = format!("replay_{}_{}", name, Local::now().timestamp_millis());
+
+    // The firmware decodes the channels of every recorded core.
+    let mut cores = recording
+        .channels
+        .iter()
+        .map(|channel| channel.core)
+        .collect::<Vec<_>>();
+    cores.sort_unstable();
+    cores.dedup();
+
+    set_terminal_panic_hook();
+    let mut app = rttui::app::App::replay(recording, opt.speed, &config, logname)?;
+    let symbols = match (config.rtt.symbolize_addresses, &elf) {
+        (true, Some(elf)) => Some(Arc::new(
+            Symbols::parse(elf).context("failed to parse the ELF file")?,
+        )),
+        _ => None,
+    };
+    for &core in &cores {
+        if let Some(defmt_state) = defmt_state {
+            app.set_defmt(core, defmt_state);
+        }
+        if let Some(symbols) = &symbols {
+            app.set_symbols(core, symbols.clone());
+        }
+    }
+    loop {
+        app.poll_rtt(None);
+        app.render();
+        if app.handle_event(None) {
+            logging::println("Shutting down.");
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Services the semihosting requests of the cores, returning the events with the index of the
+/// core in `semihosting`.
+fn poll_semihosting(
+    session: &mut Session,
+    semihosting: &mut [(usize, Semihosting)],
+) -> Result<Vec<(usize, Event)>> {
+    let mut events = Vec::new();
+    for (i, (n, semihosting)) in semihosting.iter_mut().enumerate() {
+        let event = semihosting
+            .poll(&mut session.core(*n)?)
+            .with_context(|| format!("failed to service semihosting on core {}", n))?;
+        events.extend(event.map(|event| (i, event)));
+    }
+    Ok(events)
+}
+
+/// Checks the watched cores for a crash and returns the first one found.
+fn check_faults(
+    session: &mut Session,
+    faults: &mut [(usize, FaultMonitor)],
+) -> Result<Option<Crash>> {
+    let multi_core = faults.len() > 1;
+    for (n, monitor) in faults.iter_mut() {
+        let crash = monitor
+            .check(&mut session.core(*n)?)
+            .with_context(|| format!("failed to check core {} for faults", n))?;
+        if let Some(mut crash) = crash {
+            if multi_core {
+                crash.title = format!("{} on core {}", crash.title, n);
+            }
+            return Ok(Some(crash));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the image running on `core`, or the image of the first core if it has none, e.g. as
+/// both cores run the same firmware.
+fn core_image<T>(images: &[(usize, T)], core: usize) -> &T {
+    &images
+        .iter()
+        .find(|(n, _)| *n == core)
+        .unwrap_or(&images[0])
+        .1
+}
+
+/// Resets, halts and runs cores in the order of `sequence`.
+fn run_reset_sequence(session: &mut Session, sequence: &[config::ResetStep]) -> Result<()> {
+    let halt_timeout = Duration::from_millis(500);
+    for step in sequence {
+        let mut core = session.core(step.core)?;
+        match step.action {
+            config::ResetAction::Reset => core.reset()?,
+            config::ResetAction::ResetAndHalt => {
+                core.reset_and_halt(halt_timeout)?;
+            }
+            config::ResetAction::Halt => {
+                core.halt(halt_timeout)?;
+            }
+            config::ResetAction::Run => core.run()?,
+        }
+        log::debug!("Reset sequence: {:?} core {}", step.action, step.core);
+        if let Some(delay) = step.delay {
+            std::thread::sleep(Duration::from_millis(delay));
+        }
+    }
+    Ok(())
+}
+
+/// Flashes the ELF file at `path`, showing the progress unless disabled.
+fn flash_image(
+    session: &mut Session,
+    path: &Path,
+    config: &config::Flashing,
+    progress_bars: bool,
+    chip_erase: bool,
+) -> Result<()> {
+    if progress_bars {
+        // Create progress bars.
+        let multi_progress = MultiProgress::new();
+        let style = ProgressStyle::default_bar()
+            .tick_chars("⠁⠁⠉⠙⠚⠒⠂⠂⠒⠲⠴⠤⠄⠄⠤⠠⠠⠤⠦⠖⠒⠐⠐⠒⠓⠋⠉⠈⠈✔")
+            .progress_chars("##-")
+            .template("{msg:.green.bold} {spinner} [{elapsed_precise}] [{wide_bar}] {bytes:>8}/{total_bytes:>8} @ {bytes_per_sec:>10} (eta {eta:3})")?;
+
+        // Create a new progress bar for the fill progress if filling is enabled.
+        let fill_progress = if config.restore_unwritten_bytes {
+            let fill_progress = Arc::new(multi_progress.add(ProgressBar::new(0)));
+            fill_progress.set_style(style.clone());
+            fill_progress.set_message("     Reading flash  ");
+            Some(fill_progress)
+        } else {
+            None
+        };
+
+        // Create a new progress bar for the erase progress.
+        let erase_progress = Arc::new(multi_progress.add(ProgressBar::new(0)));
+        {
+            logging::set_progress_bar(erase_progress.clone());
+        }
+        erase_progress.set_style(style.clone());
+        erase_progress.set_message("     Erasing sectors");
+
+        // Create a new progress bar for the program progress.
+        let program_progress = multi_progress.add(ProgressBar::new(0));
+        program_progress.set_style(style);
+        program_progress.set_message(" Programming pages  ");
+
+        let flash_layout_output_path = config.flash_layout_output_path.clone();
+        // Register callback to update the progress.
+        let progress = FlashProgress::new(move |event| {
+            use ProgressEvent::*;
+            match event {
+                Initialized { flash_layout } => {
+                    let total_page_size: u32 = flash_layout.pages().iter().map(|s| s.size()).sum();
+                    let total_sector_size: u64 =
+                        flash_layout.sectors().iter().map(|s| s.size()).sum();
+                    let total_fill_size: u64 = flash_layout.fills().iter().map(|s| s.size()).sum();
+                    if let Some(fp) = fill_progress.as_ref() {
+                        fp.set_length(total_fill_size)
+                    }
+                    erase_progress.set_length(total_sector_size);
+                    program_progress.set_length(total_page_size as u64);
+                    let visualizer = flash_layout.visualize();
+                    flash_layout_output_path
+                        .as_ref()
+                        .map(|path| visualizer.write_svg(path));
+                }
+                StartedProgramming => {
+                    program_progress.enable_steady_tick(Duration::from_millis(100));
+                    program_progress.reset_elapsed();
+                }
+                StartedErasing => {
+                    erase_progress.enable_steady_tick(Duration::from_millis(100));
+                    erase_progress.reset_elapsed();
+                }
+                StartedFilling => {
+                    if let Some(fp) = fill_progress.as_ref() {
+                        fp.enable_steady_tick(Duration::from_millis(100))
+                    };
+                    if let Some(fp) = fill_progress.as_ref() {
+                        fp.reset_elapsed()
+                    };
+                }
+                PageProgrammed { size, .. } => {
+                    program_progress.inc(size as u64);
+                }
+                SectorErased { size, .. } => {
+                    erase_progress.inc(size);
+                }
+                PageFilled { size, .. } => {
+                    if let Some(fp) = fill_progress.as_ref() {
+                        fp.inc(size)
+                    };
+                }
+                FailedErasing => {
+                    erase_progress.abandon();
+                    program_progress.abandon();
+                }
+                FinishedErasing => {
+                    erase_progress.finish();
+                }
+                FailedProgramming => {
+                    program_progress.abandon();
+                }
+                FinishedProgramming => {
+                    program_progress.finish();
+                }
+                FailedFilling => {
+                    if let Some(fp) = fill_progress.as_ref() {
+                        fp.abandon()
+                    };
+                }
+                FinishedFilling => {
+                    if let Some(fp) = fill_progress.as_ref() {
+                        fp.finish()
+                    };
+                }
+            }
+        });
+
+        let mut options = DownloadOptions::new();
+
+        options.progress = Some(&progress);
+        options.keep_unwritten_bytes = config.restore_unwritten_bytes;
+        options.do_chip_erase = chip_erase;
+
+        download_file_with_options(session, path, Format::Elf, options)
+            .with_context(|| format!("failed to flash {}", path.display()))?;
+
+        // If we don't do this, the inactive progress bars will swallow log
+        // messages, so they'll never be printed anywhere.
+        logging::clear_progress_bar();
+    } else {
+        let mut options = DownloadOptions::new();
+        options.keep_unwritten_bytes = config.restore_unwritten_bytes;
+        options.do_chip_erase = chip_erase;
+
+        download_file_with_options(session, path, Format::Elf, options)
+            .with_context(|| format!("failed to flash {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Returns where the RTT control block is searched for, looking up its symbol in the ELF file
+/// `elf` if configured.
+fn rtt_scan_region(control_block: &config::ControlBlock, elf: &[u8]) -> ScanRegion {
+    match control_block {
+        config::ControlBlock::Symbol(name) => match rtt_symbol(elf, name) {
+            Some(address) => ScanRegion::Exact(address as u32),
+            None => ScanRegion::Ram,
+        },
+        config::ControlBlock::Address(address) => ScanRegion::Exact(*address),
+        config::ControlBlock::Range { start, end } => ScanRegion::Range(*start..*end),
+    }
+}
+
+/// Returns the address of the symbol `name` in the ELF file `elf`, usually the RTT control block
+/// `_SEGGER_RTT`.
+fn rtt_symbol(elf: &[u8], name: &str) -> Option<u64> {
+    if let Ok(binary) = goblin::elf::Elf::parse(elf) {
+        for sym in &binary.syms {
+            if binary.strtab.get_at(sym.st_name) == Some(name) {
+                return Some(sym.st_value);
+            }
+        }
+    }
+
+    log::warn!(
+        "No RTT header info ({}) was present in the ELF file. Does your firmware run RTT?",
+        name
+    );
+    None
+}
+
+/// Creates a tab for output which does not come from RTT.
+fn output_tab(output: &MemoryChannel, config: &config::Config) -> ChannelState {
+    ChannelState::new(
+        Some(Box::new(output.clone())),
+        None,
+        None,
+        config.rtt.show_timestamps,
+        DataFormat::String,
+        None,
+    )
+}
+
+/// Ends cargo-embed with the exit code the target passed to semihosting.
+fn exit_with_target_code(code: i32) -> ! {
+    logging::println(format!(
+        "        {} target exited with code {}",
+        "Done".green().bold(),
+        code
+    ));
+    process::exit(code);
+}
+
+/// Loads the defmt table and, if complete, the locations from the ELF file `elf`.
+///
+/// The table is kept until the program exits, as the decoders of the channels borrow it.
+fn load_defmt(elf: &[u8]) -> Result<Option<&'static DefmtState>> {
+    if let Some(table) = defmt_decoder::Table::parse(elf)? {
+        let locs = {
+            let locs = table.get_locations(elf)?;
+
+            if !table.is_empty() && locs.is_empty() {
+                log::warn!("Insufficient DWARF info; compile your program with `debug = 2` to enable location info.");
+                None
+            } else if table.indices().all(|idx| locs.contains_key(&(idx as u64))) {
+                Some(locs)
+            } else {
+                log::warn!("Location info is incomplete; it will be omitted from the output.");
+                None
+            }
+        };
+        Ok(Some(Box::leak(Box::new((table, locs)))))
+    } else {
+        log::error!(
+            "Defmt enabled in rtt channel config, but defmt table couldn't be loaded from binary."
+        );
+        Ok(None)
+    }
+}
+
+/// `App` puts the terminal into a special state, as required by the text-based UI. If a panic
+/// happens while the terminal is in that state, this will completely mess up the user's terminal
+/// (misformatted panic message, newlines being ignored, input characters not being echoed, ...).
+///
+/// This sets a panic hook which cleans up the terminal, while otherwise preserving the behavior
+/// of the default panic hook (or whichever custom hook might have been registered before).
+fn set_terminal_panic_hook() {
+    let previous_panic_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |panic_info| {
+        rttui::app::clean_up_terminal();
+        previous_panic_hook(panic_info);
+    }));
+}
+
 fn print_families() -> Result<()> {
     logging::println("Available chips:");
     for family in
@@ -1,41 +1,97 @@
 use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Local};
 use crossterm::{
-    event::{self, KeyCode},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event as CEvent, KeyCode},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use probe_rs::Core;
+use probe_rs::{Core, CoreStatus, Session};
 use probe_rs_rtt::RttChannel;
-use std::{fmt::write, path::PathBuf, sync::mpsc::RecvTimeoutError};
 use std::{
-    io::{Read, Seek, Write},
-    time::Duration,
+    io::{Stdout, Write},
+    time::{Duration, Instant},
+};
+use std::{
+    path::PathBuf,
+    sync::{mpsc::RecvTimeoutError, Arc},
 };
 use tui::{
-    backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
-    style::{Color, Modifier, Style},
+    backend::{Backend, CrosstermBackend},
+    buffer::Buffer,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    symbols,
     text::{Span, Spans},
-    widgets::{Block, Borders, List, ListItem, Paragraph, Tabs},
-    Terminal,
+    widgets::{
+        Axis, Block, Borders, Chart, Clear, Dataset, GraphType, List, ListItem, Paragraph, Tabs,
+    },
+    Frame, Terminal,
 };
 
+use crate::{fault::Crash, symbols::Symbols};
+
 use super::{
-    channel::{ChannelState, DataFormat},
-    event::Events,
+    channel::{ChannelConfig, ChannelState, DataFormat, DefmtState},
+    event::{EventSource, Events},
+    input::LineEditor,
+    keys::{Action, KeyBindings},
+    layout::{self, SplitLayout},
+    mouse::{self, Highlight, Selection},
+    plot::PlotState,
+    recording::{Recorder, Recording},
+    scrollback::Line,
+    source::{DownSink, RttUpChannel, UpSource},
+    status::{self, SessionInfo, STATUS_INTERVAL},
+    theme::{self, Theme},
 };
 
-use event::KeyModifiers;
+use event::{KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
 
 /// App holds the state of the application
-pub struct App {
+///
+/// It draws to a terminal with backend `B` and handles the events of `E`, which are the real
+/// terminal unless the UI is driven by tests.
+pub struct App<B: Backend = CrosstermBackend<Stdout>, E: EventSource = Events> {
     tabs: Vec<ChannelState>,
     current_tab: usize,
+    /// How the visible tabs are arranged.
+    split: SplitLayout,
+    /// The tabs shown when the screen is split.
+    panes: Vec<usize>,
+    /// Counts the calls to [App::poll_rtt], to merge channels in receive order.
+    poll_round: u64,
+    /// Where the tab bar and the visible tabs were last rendered, to handle mouse clicks.
+    tab_bar_area: Rect,
+    pane_areas: Vec<(usize, Rect)>,
+    /// The text selection while dragging the mouse.
+    selection: Option<Selection>,
+    /// The last rendered frame, kept while selecting text.
+    last_frame: Option<Buffer>,
+    keys: KeyBindings,
+    theme: Theme,
+    /// Whether the key bindings are shown.
+    show_help: bool,
+    /// The crash of the firmware, printed again when the UI is closed.
+    crash: Option<Crash>,
+    /// Whether the crash is shown on top of the tabs.
+    show_crash: bool,
+    /// The annotation of the next marker, entered in place of the status bar.
+    marker_input: LineEditor,
+    /// Whether the annotation of a marker is being entered.
+    marker_prompt: bool,
+    /// The number of markers inserted so far.
+    markers: usize,
+    session_info: SessionInfo,
+    core_status: Option<CoreStatus>,
+    /// When the statistics in the status bar were last updated.
+    last_status_update: Instant,
 
-    terminal: Terminal<CrosstermBackend<std::io::Stdout>>,
-    events: Events,
+    terminal: Terminal<B>,
+    events: E,
     history_path: Option<PathBuf>,
     logname: String,
+    /// Records the data received on all up channels, while logging is enabled.
+    recorder: Option<Recorder>,
 }
 
 fn pull_channel<C: RttChannel>(channels: &mut Vec<C>, n: usize) -> Option<C> {
@@ -47,69 +103,200 @@ fn pull_channel<C: RttChannel>(channels: &mut Vec<C>, n: usize) -> Option<C> {
     c.map(|c| channels.remove(c))
 }
 
+/// Creates the tabs for the channels of the RTT control block of core `core`.
+fn rtt_tabs(
+    mut rtt: probe_rs_rtt::Rtt,
+    core: usize,
+    config: &crate::config::Config,
+    start: DateTime<Local>,
+) -> Vec<ChannelState> {
+    let mut tabs = Vec::new();
+    // Channels configured for another core are skipped.
+    let channels = config
+        .rtt
+        .channels
+        .iter()
+        .filter(|channel| channel.core.unwrap_or(core) == core)
+        .collect::<Vec<_>>();
+    if !channels.is_empty() {
+        let mut up_channels = rtt.up_channels().drain().collect::<Vec<_>>();
+        let mut down_channels = rtt.down_channels().drain().collect::<Vec<_>>();
+        for channel in channels {
+            let mut tab = ChannelState::new(
+                channel
+                    .up
+                    .and_then(|up| pull_channel(&mut up_channels, up))
+                    .map(|up| Box::new(RttUpChannel::new(up)) as Box<dyn UpSource>),
+                channel
+                    .down
+                    .and_then(|down| pull_channel(&mut down_channels, down))
+                    .map(|down| Box::new(down) as Box<dyn DownSink>),
+                channel.name.clone(),
+                config.rtt.show_timestamps,
+                channel.format,
+                channel.plot.clone(),
+            );
+            tab.input_mut().set_completions(channel.commands.clone());
+            tab.set_line_ending(channel.line_ending);
+            apply_channel_options(&mut tab, Some(channel), &config.rtt, start);
+            tabs.push(tab);
+        }
+    } else {
+        let up_channels = rtt.up_channels().drain();
+        let mut down_channels = rtt.down_channels().drain().collect::<Vec<_>>();
+        for channel in up_channels {
+            let number = channel.number();
+            tabs.push(ChannelState::new(
+                Some(Box::new(RttUpChannel::new(channel))),
+                pull_channel(&mut down_channels, number)
+                    .map(|down| Box::new(down) as Box<dyn DownSink>),
+                None,
+                config.rtt.show_timestamps,
+                DataFormat::String,
+                None,
+            ));
+        }
+
+        for channel in down_channels {
+            tabs.push(ChannelState::new(
+                None,
+                Some(Box::new(channel)),
+                None,
+                config.rtt.show_timestamps,
+                DataFormat::String,
+                None,
+            ));
+        }
+
+        for tab in &mut tabs {
+            apply_channel_options(tab, None, &config.rtt, start);
+        }
+    }
+    tabs
+}
+
 impl App {
+    /// Creates the UI for the channels of the RTT control block of each core in `rtts`, followed
+    /// by `other_tabs`, e.g. for semihosting. With several cores, the tabs are labelled by core.
     pub fn new(
-        mut rtt: probe_rs_rtt::Rtt,
+        rtts: Vec<(usize, probe_rs_rtt::Rtt)>,
+        other_tabs: Vec<ChannelState>,
         config: &crate::config::Config,
         logname: String,
+        session_info: SessionInfo,
     ) -> Result<Self> {
+        let start = Local::now();
+        let label = rtts.len() > 1;
         let mut tabs = Vec::new();
-        if !config.rtt.channels.is_empty() {
-            let mut up_channels = rtt.up_channels().drain().collect::<Vec<_>>();
-            let mut down_channels = rtt.down_channels().drain().collect::<Vec<_>>();
-            for channel in &config.rtt.channels {
-                tabs.push(ChannelState::new(
-                    channel.up.and_then(|up| pull_channel(&mut up_channels, up)),
-                    channel
-                        .down
-                        .and_then(|down| pull_channel(&mut down_channels, down)),
-                    channel.name.clone(),
-                    config.rtt.show_timestamps,
-                    channel.format,
-                ))
-            }
-        } else {
-            let up_channels = rtt.up_channels().drain();
-            let mut down_channels = rtt.down_channels().drain().collect::<Vec<_>>();
-            for channel in up_channels {
-                let number = channel.number();
-                tabs.push(ChannelState::new(
-                    Some(channel),
-                    pull_channel(&mut down_channels, number),
-                    None,
-                    config.rtt.show_timestamps,
-                    DataFormat::String,
-                ));
+        for (core, rtt) in rtts {
+            let mut core_tabs = rtt_tabs(rtt, core, config, start);
+            for tab in &mut core_tabs {
+                tab.set_core(core);
+                if label {
+                    let name = format!("Core {}: {}", core, tab.name());
+                    tab.set_name(name);
+                }
             }
+            tabs.extend(core_tabs);
+        }
 
-            for channel in down_channels {
-                tabs.push(ChannelState::new(
-                    None,
-                    Some(channel),
-                    None,
-                    config.rtt.show_timestamps,
-                    DataFormat::String,
-                ));
-            }
+        for mut tab in other_tabs {
+            apply_channel_options(&mut tab, None, &config.rtt, start);
+            tabs.push(tab);
         }
 
-        // Code farther down relies on tabs being configured and might panic
-        // otherwise.
-        if tabs.is_empty() {
-            return Err(anyhow!(
-                "Failed to initialize RTT UI: No RTT channels configured"
-            ));
+        let mut app = Self::from_tabs(tabs, config, logname, session_info)?;
+        app.start_recording();
+        Ok(app)
+    }
+
+    /// Creates the UI for replaying `recording`, `speed` times as fast as it was recorded.
+    ///
+    /// The options of configured channels are applied to the recorded channels of the same name.
+    pub fn replay(
+        recording: Recording,
+        speed: f64,
+        config: &crate::config::Config,
+        logname: String,
+    ) -> Result<Self> {
+        let start = recording.start;
+        let session_info = recording.session.clone();
+        let mut tabs = Vec::new();
+        for (channel, replay) in recording.into_channels(speed) {
+            let channel_config = config
+                .rtt
+                .channels
+                .iter()
+                .find(|config| config.name.as_ref() == Some(&channel.name));
+            let mut tab = ChannelState::new(
+                Some(Box::new(replay)),
+                None,
+                Some(channel.name),
+                config.rtt.show_timestamps,
+                channel.format,
+                channel.plot,
+            );
+            tab.set_core(channel.core);
+            apply_channel_options(&mut tab, channel_config, &config.rtt, start);
+            tabs.push(tab);
         }
 
+        Self::from_tabs(tabs, config, logname, session_info)
+    }
+
+    /// Creates the UI on the terminal of the process, which is switched to raw mode.
+    fn from_tabs(
+        tabs: Vec<ChannelState>,
+        config: &crate::config::Config,
+        logname: String,
+        session_info: SessionInfo,
+    ) -> Result<Self> {
+        // Checked before the terminal is switched to raw mode, so the error stays readable.
+        check_tabs(&tabs)?;
+
         let events = Events::new();
 
         enable_raw_mode().context("Failed to enable 'raw' mode for terminal")?;
         let mut stdout = std::io::stdout();
         execute!(stdout, EnterAlternateScreen).unwrap();
+        if config.rtt.mouse {
+            execute!(stdout, EnableMouseCapture).unwrap();
+        }
         let backend = CrosstermBackend::new(stdout);
         let mut terminal = Terminal::new(backend).unwrap();
         let _ = terminal.hide_cursor();
 
+        Self::with_terminal(tabs, config, logname, session_info, terminal, events)
+    }
+}
+
+impl<B: Backend, E: EventSource> App<B, E> {
+    /// Creates the UI for `tabs`, drawn to `terminal` and driven by `events`.
+    pub fn with_terminal(
+        mut tabs: Vec<ChannelState>,
+        config: &crate::config::Config,
+        logname: String,
+        session_info: SessionInfo,
+        terminal: Terminal<B>,
+        events: E,
+    ) -> Result<Self> {
+        check_tabs(&tabs)?;
+
+        if config.rtt.input_history {
+            for tab in tabs.iter_mut().filter(|t| t.has_down_channel()) {
+                let name = sanitize_log_name(format!("{}.history", tab.name()));
+                let path = config.rtt.input_history_path.join(name);
+                tab.input_mut().set_history_file(path);
+            }
+        }
+
+        // Interleaving is only useful with at least two channels receiving data.
+        if config.rtt.all_tab && tabs.iter().filter(|t| t.has_up_channel()).count() > 1 {
+            let mut tab = ChannelState::new_merged("All".to_owned(), config.rtt.show_timestamps);
+            tab.set_scrollback_limit(config.rtt.scrollback);
+            tabs.push(tab);
+        }
+
         let history_path = {
             if !config.rtt.log_enabled {
                 None
@@ -126,349 +313,722 @@ impl App {
             }
         };
 
+        let panes = layout::initial_panes(&config.rtt.split_tabs, tabs.len());
+
         Ok(Self {
+            current_tab: if config.rtt.split == SplitLayout::None {
+                0
+            } else {
+                panes.first().copied().unwrap_or(0)
+            },
             tabs,
-            current_tab: 0,
+            split: config.rtt.split,
+            panes,
+            poll_round: 0,
+            tab_bar_area: Rect::default(),
+            pane_areas: Vec::new(),
+            selection: None,
+            last_frame: None,
+            keys: config.rtt.keys.clone(),
+            theme: Theme::new(&config.rtt.theme, theme::no_color()),
+            show_help: false,
+            crash: None,
+            show_crash: false,
+            marker_input: LineEditor::new(),
+            marker_prompt: false,
+            markers: 0,
+            session_info,
+            core_status: None,
+            last_status_update: Instant::now(),
             terminal,
             events,
             history_path,
             logname,
+            recorder: None,
         })
     }
 
-    pub fn get_rtt_symbol<T: Read + Seek>(file: &mut T) -> Option<u64> {
-        let mut buffer = Vec::new();
-        if file.read_to_end(&mut buffer).is_ok() {
-            if let Ok(binary) = goblin::elf::Elf::parse(buffer.as_slice()) {
-                for sym in &binary.syms {
-                    if let Some(name) = binary.strtab.get_at(sym.st_name) {
-                        if name == "_SEGGER_RTT" {
-                            return Some(sym.st_value);
-                        }
+    /// Records the data of all up channels next to the logs, if logging is enabled.
+    fn start_recording(&mut self) {
+        let path = match &self.history_path {
+            Some(path) => path.join(sanitize_log_name(format!(
+                "{}_recording.jsonl",
+                self.logname
+            ))),
+            None => return,
+        };
+        let channels = self
+            .tabs
+            .iter()
+            .filter_map(ChannelState::recorded_channel)
+            .collect();
+        match Recorder::create(&path, &self.session_info, channels) {
+            Ok(recorder) => self.recorder = Some(recorder),
+            Err(e) => log::warn!("Could not create the recording {}: {}", path.display(), e),
+        }
+    }
+
+    pub fn render(&mut self) {
+        let prefixes = self
+            .tabs
+            .iter()
+            .map(|t| format!("[{}] ", t.name()))
+            .collect::<Vec<_>>();
+        let panes = self.visible_panes();
+        let tabs = &mut self.tabs;
+        let current_tab = self.current_tab;
+        let split = self.split;
+        let theme = &self.theme;
+        let status = status::status_line(
+            &self.session_info,
+            self.core_status,
+            self.history_path.is_some(),
+            tabs.iter()
+                .filter(|t| t.has_up_channel())
+                .map(|t| (t.name(), t.stats())),
+        );
+        let help = if self.show_help {
+            Some(self.keys.help())
+        } else {
+            None
+        };
+        let crash = if self.show_crash {
+            self.crash.as_ref()
+        } else {
+            None
+        };
+        let selection = self.selection;
+        let marker_input = if self.marker_prompt {
+            Some(&self.marker_input)
+        } else {
+            None
+        };
+        let mut sizes = Vec::new();
+        let mut tab_bar_area = Rect::default();
+        let mut pane_areas = Vec::new();
+
+        let frame = self
+            .terminal
+            .draw(|f| {
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .margin(0)
+                    .constraints(
+                        [
+                            Constraint::Length(1),
+                            Constraint::Min(1),
+                            Constraint::Length(1),
+                        ]
+                        .as_ref(),
+                    )
+                    .split(f.size());
+
+                let tab_names = tabs
+                    .iter()
+                    .map(|t| Spans::from(t.name()))
+                    .collect::<Vec<_>>();
+                let tab_bar = Tabs::new(tab_names)
+                    .select(current_tab)
+                    .style(theme.tab_bar)
+                    .highlight_style(theme.selected_tab);
+                f.render_widget(tab_bar, chunks[0]);
+                tab_bar_area = chunks[0];
+
+                for (&tab, area) in panes.iter().zip(split.areas(chunks[1], panes.len())) {
+                    pane_areas.push((tab, area));
+                    let pane = Pane {
+                        bordered: split != SplitLayout::None,
+                        focused: tab == current_tab,
+                    };
+                    if let Some(size) =
+                        render_channel(f, &mut tabs[tab], &prefixes, area, pane, theme)
+                    {
+                        sizes.push((tab, size));
                     }
                 }
-            }
+
+                if let Some(input) = marker_input {
+                    let prompt = " Marker annotation: ";
+                    let width = chunks[2].width as usize;
+                    let (line, cursor) = input.view(width.saturating_sub(prompt.len()));
+                    let input = Paragraph::new(Spans::from(vec![
+                        Span::styled(prompt, theme.input.add_modifier(Modifier::BOLD)),
+                        Span::raw(line),
+                    ]))
+                    .style(theme.input);
+                    f.render_widget(input, chunks[2]);
+                    let x = chunks[2].x + (prompt.len() + cursor).min(width) as u16;
+                    f.set_cursor(x, chunks[2].y);
+                } else {
+                    let status = Paragraph::new(Spans::from(vec![Span::raw(status)]))
+                        .style(theme.status_bar);
+                    f.render_widget(status, chunks[2]);
+                }
+
+                if let Some(help) = &help {
+                    render_help(f, help);
+                }
+
+                if let Some(crash) = crash {
+                    render_crash(f, crash);
+                }
+
+                if let Some(selection) = selection {
+                    for row in selection.rows(f.size()) {
+                        f.render_widget(Highlight, row);
+                    }
+                }
+            })
+            .unwrap();
+
+        if selection.is_some() {
+            self.last_frame = Some(frame.buffer.clone());
         }
+        self.tab_bar_area = tab_bar_area;
+        self.pane_areas = pane_areas;
 
-        log::warn!("No RTT header info was present in the ELF file. Does your firmware run RTT?");
-        None
+        for (tab, (message_num, height)) in sizes {
+            let scroll_offset = self.tabs[tab].scroll_offset();
+            if message_num < height + scroll_offset {
+                self.tabs[tab].set_scroll_offset(message_num - height.min(message_num));
+            }
+        }
     }
 
-    pub fn render(
-        &mut self,
-        defmt_state: &Option<(defmt_decoder::Table, Option<defmt_decoder::Locations>)>,
-    ) {
-        let input = self.current_tab().input().to_owned();
-        let has_down_channel = self.current_tab().has_down_channel();
-        let scroll_offset = self.current_tab().scroll_offset();
-        let messages = self.current_tab().messages().clone();
-        let data = self.current_tab().data().clone();
+    /// Returns the indices of the tabs which are currently visible.
+    fn visible_panes(&self) -> Vec<usize> {
+        if self.split == SplitLayout::None {
+            vec![self.current_tab]
+        } else {
+            self.panes.clone()
+        }
+    }
 
-        log::debug!("Data length: {}", data.len());
+    /// Selects the given tab. When several tabs are shown at once and the tab is not visible
+    /// yet, it replaces the tab in the focused pane.
+    fn select_tab(&mut self, n: usize) {
+        if n >= self.tabs.len() {
+            return;
+        }
 
-        let tabs = &self.tabs;
         let current_tab = self.current_tab;
-        let mut height = 0;
-        let mut messages_wrapped: Vec<String> = Vec::new();
-
-        match tabs[current_tab].format() {
-            DataFormat::String => {
-                self.terminal
-                    .draw(|f| {
-                        let constraints = if has_down_channel {
-                            &[
-                                Constraint::Length(1),
-                                Constraint::Min(1),
-                                Constraint::Length(1),
-                            ][..]
-                        } else {
-                            &[Constraint::Length(1), Constraint::Min(1)][..]
-                        };
-                        let chunks = Layout::default()
-                            .direction(Direction::Vertical)
-                            .margin(0)
-                            .constraints(constraints)
-                            .split(f.size());
-
-                        let tab_names = tabs
-                            .iter()
-                            .map(|t| Spans::from(t.name()))
-                            .collect::<Vec<_>>();
-                        let tabs = Tabs::new(tab_names)
-                            .select(current_tab)
-                            .style(Style::default().fg(Color::Black).bg(Color::Yellow))
-                            .highlight_style(
-                                Style::default()
-                                    .fg(Color::Green)
-                                    .bg(Color::Yellow)
-                                    .add_modifier(Modifier::BOLD),
-                            );
-                        f.render_widget(tabs, chunks[0]);
-
-                        height = chunks[1].height as usize;
-
-                        // We need to collect to generate message_num :(
-                        messages_wrapped = messages
-                            .iter()
-                            .flat_map(|m| textwrap::wrap(m, chunks[1].width as usize))
-                            .map(|s| s.into_owned())
-                            .collect();
-
-                        let message_num = messages_wrapped.len();
-
-                        let messages: Vec<ListItem> = messages_wrapped
-                            .iter()
-                            .skip(message_num - (height + scroll_offset).min(message_num))
-                            .take(height)
-                            .map(|s| ListItem::new(vec![Spans::from(Span::raw(s))]))
-                            .collect();
-
-                        let messages = List::new(messages.as_slice())
-                            .block(Block::default().borders(Borders::NONE));
-                        f.render_widget(messages, chunks[1]);
-
-                        if has_down_channel {
-                            let input = Paragraph::new(Spans::from(vec![Span::raw(input.clone())]))
-                                .style(Style::default().fg(Color::Yellow).bg(Color::Blue));
-                            f.render_widget(input, chunks[2]);
-                        }
-                    })
-                    .unwrap();
-
-                let message_num = messages_wrapped.len();
-                let scroll_offset = self.tabs[self.current_tab].scroll_offset();
-                if message_num < height + scroll_offset {
-                    self.current_tab_mut()
-                        .set_scroll_offset(message_num - height.min(message_num));
-                }
+        if !self.panes.contains(&n) {
+            if let Some(pane) = self.panes.iter_mut().find(|t| **t == current_tab) {
+                *pane = n;
             }
-            binle_or_defmt => {
-                self.terminal
-                    .draw(|f| {
-                        let constraints = if has_down_channel {
-                            &[
-                                Constraint::Length(1),
-                                Constraint::Min(1),
-                                Constraint::Length(1),
-                            ][..]
-                        } else {
-                            &[Constraint::Length(1), Constraint::Min(1)][..]
-                        };
-                        let chunks = Layout::default()
-                            .direction(Direction::Vertical)
-                            .margin(0)
-                            .constraints(constraints)
-                            .split(f.size());
-
-                        let tab_names = tabs
-                            .iter()
-                            .map(|t| Spans::from(t.name()))
-                            .collect::<Vec<_>>();
-                        let tabs = Tabs::new(tab_names)
-                            .select(current_tab)
-                            .style(Style::default().fg(Color::Black).bg(Color::Yellow))
-                            .highlight_style(
-                                Style::default()
-                                    .fg(Color::Green)
-                                    .bg(Color::Yellow)
-                                    .add_modifier(Modifier::BOLD),
-                            );
-                        f.render_widget(tabs, chunks[0]);
-
-                        height = chunks[1].height as usize;
-
-                        // probably pretty bad
-                        match binle_or_defmt {
-                            DataFormat::BinaryLE => {
-                                messages_wrapped.push(data.iter().fold(
-                                    String::new(),
-                                    |mut output, byte| {
-                                        let _ = write(&mut output, format_args!("{:#04x}, ", byte));
-                                        output
-                                    },
-                                ));
-                            }
-                            DataFormat::Defmt => {
-                                let (table, locs) = defmt_state.as_ref().expect(
-                                "Running rtt in defmt mode but table or locations could not be loaded.",
-                            );
-                                let mut stream_decoder = table.new_stream_decoder();
-                                stream_decoder.received(&data);
-                                while let Ok(frame) = stream_decoder.decode()
-                                {
-                                    // NOTE(`[]` indexing) all indices in `table` have already been
-                                    // verified to exist in the `locs` map.
-                                    let loc = locs.as_ref().map(|locs| &locs[&frame.index()]);
-
-                                    messages_wrapped.push(format!("{}", frame.display(false)));
-                                    if let Some(loc) = loc {
-                                        let relpath = if let Ok(relpath) =
-                                            loc.file.strip_prefix(&std::env::current_dir().unwrap())
-                                        {
-                                            relpath
-                                        } else {
-                                            // not relative; use full path
-                                            &loc.file
-                                        };
-
-                                        messages_wrapped.push(format!(
-                                            "└─ {}:{}",
-                                            relpath.display(),
-                                            loc.line
-                                        ));
-                                    }
-                                }
-                            }
-                            DataFormat::String => unreachable!("You encountered a bug. Please open an issue on Github."),
-                        }
+        }
+        self.current_tab = n;
+    }
 
-                        let message_num = messages_wrapped.len();
+    /// Moves the input focus to the next visible pane.
+    fn focus_next_pane(&mut self) {
+        if self.split == SplitLayout::None {
+            return;
+        }
 
-                        let messages: Vec<ListItem> = messages_wrapped
-                            .iter()
-                            .skip(message_num - (height + scroll_offset).min(message_num))
-                            .take(height)
-                            .map(|s| ListItem::new(vec![Spans::from(Span::raw(s))]))
-                            .collect();
+        let next = self
+            .panes
+            .iter()
+            .position(|&t| t == self.current_tab)
+            .map_or(0, |i| (i + 1) % self.panes.len());
+        self.current_tab = self.panes[next];
+    }
 
-                        let messages = List::new(messages.as_slice())
-                            .block(Block::default().borders(Borders::NONE));
-                        f.render_widget(messages, chunks[1]);
+    /// Switches to the next split layout.
+    fn cycle_split(&mut self) {
+        self.split = self.split.next();
+        if self.split != SplitLayout::None && !self.panes.contains(&self.current_tab) {
+            match self.panes.first_mut() {
+                Some(pane) => *pane = self.current_tab,
+                None => self.panes.push(self.current_tab),
+            }
+        }
+    }
 
-                        if has_down_channel {
-                            let input = Paragraph::new(Spans::from(vec![Span::raw(input.clone())]))
-                                .style(Style::default().fg(Color::Yellow).bg(Color::Blue));
-                            f.render_widget(input, chunks[2]);
-                        }
-                    })
-                    .unwrap();
-
-                let message_num = messages_wrapped.len();
-                let scroll_offset = self.tabs[self.current_tab].scroll_offset();
-                if message_num < height + scroll_offset {
-                    self.current_tab_mut()
-                        .set_scroll_offset(message_num - height.min(message_num));
-                }
+    /// Handles a key press while the plot view of the current tab is shown.
+    fn handle_plot_key(&mut self, c: char) {
+        if c == 's' {
+            self.export_plot();
+        } else if let Some(plot) = self.current_tab_mut().plot_mut() {
+            match c {
+                '+' => plot.zoom_in(),
+                '-' => plot.zoom_out(),
+                ' ' => plot.toggle_pause(),
+                _ => {}
             }
         }
     }
 
+    /// Exports the visible window of the current plot as CSV into the log directory.
+    fn export_plot(&mut self) {
+        let name = format!("{}_channel{}_plot.csv", self.logname, self.current_tab);
+        let final_path = self
+            .history_path
+            .clone()
+            .unwrap_or_default()
+            .join(sanitize_log_name(name));
+
+        if let Some(plot) = self.current_tab_mut().plot_mut() {
+            let status = match plot.export_csv(&final_path) {
+                Ok(_) => format!("Exported to {}", final_path.display()),
+                Err(e) => format!("Could not export to {}: {}", final_path.display(), e),
+            };
+            plot.set_status(status);
+        }
+    }
+
     /// Returns true if the application should exit.
-    pub fn handle_event(&mut self, core: &mut Core) -> bool {
+    pub fn handle_event(&mut self, session: Option<&mut Session>) -> bool {
         match self.events.next(Duration::from_millis(10)) {
-            Ok(event) => match event.code {
-                KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::CONTROL) => {
-                    clean_up_terminal();
-                    let _ = self.terminal.show_cursor();
-
-                    if let Some(path) = &self.history_path {
-                        for (i, tab) in self.tabs.iter().enumerate() {
-                            if tab.format() == DataFormat::Defmt {
-                                eprintln!("Not saving tab {} as saving defmt logs is currently unsupported.", i + 1);
-                                continue;
-                            }
+            Ok(CEvent::Key(event)) => {
+                self.current_tab_mut().clear_notice();
+                self.handle_key(event, session)
+            }
+            Ok(CEvent::Mouse(event)) => {
+                self.handle_mouse(event);
+                false
+            }
+            Ok(_) => false,
+            Err(RecvTimeoutError::Disconnected) => {
+                log::warn!("Unable to receive anymore input events from terminal, shutting down.");
+                true
+            }
+            // Timeout just means no input received.
+            Err(RecvTimeoutError::Timeout) => false,
+        }
+    }
 
-                            let extension = match tab.format() {
-                                DataFormat::String => "txt",
-                                DataFormat::BinaryLE => "dat",
-                                DataFormat::Defmt => unreachable!(),
-                            };
-
-                            let name = format!("{}_channel{}.{}", self.logname, i, extension);
-                            let sanitize_options = sanitize_filename::Options {
-                                replacement: "_",
-                                ..Default::default()
-                            };
-                            let sanitized_name =
-                                sanitize_filename::sanitize_with_options(name, sanitize_options);
-                            let final_path = path.join(sanitized_name);
-
-                            match std::fs::File::create(&final_path) {
-                                Ok(mut file) => {
-                                    match tab.format() {
-                                        DataFormat::String => {
-                                            for line in tab.messages() {
-                                                match writeln!(file, "{}", line) {
-                                                    Ok(_) => {}
-                                                    Err(e) => {
-                                                        eprintln!(
-                                                            "\nError writing log channel {}: {}",
-                                                            i, e
-                                                        );
-                                                        continue;
-                                                    }
-                                                }
-                                            }
-                                        }
-                                        DataFormat::BinaryLE => match file.write(tab.data()) {
-                                            Ok(_) => {}
-                                            Err(e) => {
-                                                eprintln!(
-                                                    "\nError writing log channel {}: {}",
-                                                    i, e
-                                                );
-                                                continue;
-                                            }
-                                        },
-                                        DataFormat::Defmt => unreachable!(),
-                                    };
+    fn handle_mouse(&mut self, event: MouseEvent) {
+        let (column, row) = (event.column, event.row);
+        let pane = self
+            .pane_areas
+            .iter()
+            .find(|(_, area)| contains(area, column, row))
+            .map(|(tab, _)| *tab);
+
+        match event.kind {
+            MouseEventKind::ScrollUp => {
+                if let Some(tab) = pane {
+                    self.tabs[tab].scroll_up(SCROLL_LINES);
+                }
+            }
+            MouseEventKind::ScrollDown => {
+                if let Some(tab) = pane {
+                    self.tabs[tab].scroll_down(SCROLL_LINES);
+                }
+            }
+            MouseEventKind::Down(MouseButton::Left) => {
+                if contains(&self.tab_bar_area, column, row) {
+                    let names = self.tabs.iter().map(|t| t.name());
+                    if let Some(tab) = mouse::tab_at(names, self.tab_bar_area.x, column) {
+                        self.select_tab(tab);
+                    }
+                } else {
+                    if let Some(tab) = pane {
+                        self.current_tab = tab;
+                    }
+                    self.selection = Some(Selection::new(column, row));
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                if let Some(selection) = self.selection.as_mut() {
+                    selection.cursor = (column, row);
+                }
+            }
+            MouseEventKind::Up(MouseButton::Left) => {
+                if let (Some(selection), Some(frame)) = (self.selection.take(), &self.last_frame) {
+                    if !selection.is_empty() {
+                        if let Err(e) = mouse::copy_to_clipboard(&selection.text(frame)) {
+                            log::warn!("Could not copy the selection: {}", e);
+                        }
+                    }
+                }
+                self.last_frame = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Decodes the frames received on defmt channels of `core` with `state`.
+    pub fn set_defmt(&mut self, core: usize, state: &'static DefmtState) {
+        for tab in &mut self.tabs {
+            if tab.core() == core && tab.format() == DataFormat::Defmt {
+                tab.set_defmt(state);
+            }
+        }
+    }
+
+    /// Annotates the addresses of functions in the lines received on text and defmt channels of
+    /// `core`.
+    pub fn set_symbols(&mut self, core: usize, symbols: Arc<Symbols>) {
+        for tab in &mut self.tabs {
+            if tab.core() == core && tab.has_up_channel() && tab.format() != DataFormat::BinaryLE {
+                tab.set_symbols(symbols.clone());
+            }
+        }
+    }
+
+    /// Shows `crash` on top of the tabs until a key is pressed.
+    pub fn show_crash(&mut self, crash: Crash) {
+        self.crash = Some(crash);
+        self.show_crash = true;
+    }
+
+    /// Restores the terminal and saves the logs, if logging is enabled.
+    pub fn quit(&mut self) {
+        clean_up_terminal();
+        let _ = self.terminal.show_cursor();
+        self.flush_recording();
+
+        if let Some(crash) = &self.crash {
+            eprintln!("{}", crash.title);
+            for line in &crash.lines {
+                eprintln!("{}", line);
+            }
+        }
 
-                                    // Flush file
-                                    if let Err(e) = file.flush() {
-                                        eprintln!("Error writing log channel {}: {}", i, e)
+        if let Some(path) = &self.history_path {
+            for (i, tab) in self.tabs.iter().enumerate() {
+                if tab.is_merged() {
+                    continue;
+                }
+
+                if tab.format() == DataFormat::Defmt {
+                    eprintln!(
+                        "Not saving tab {} as saving defmt logs is currently unsupported.",
+                        i + 1
+                    );
+                    continue;
+                }
+
+                let extension = match tab.format() {
+                    DataFormat::String => "txt",
+                    DataFormat::BinaryLE => "dat",
+                    DataFormat::Defmt => unreachable!(),
+                };
+
+                let name = format!("{}_channel{}.{}", self.logname, i, extension);
+                let final_path = path.join(sanitize_log_name(name));
+
+                match std::fs::File::create(&final_path) {
+                    Ok(mut file) => {
+                        match tab.format() {
+                            DataFormat::String => {
+                                for line in tab.scrollback().lines() {
+                                    match writeln!(file, "{}", line.text) {
+                                        Ok(_) => {}
+                                        Err(e) => {
+                                            eprintln!("\nError writing log channel {}: {}", i, e);
+                                            continue;
+                                        }
+                                    }
+                                }
+                            }
+                            DataFormat::BinaryLE => {
+                                let (front, back) = tab.data().as_slices();
+                                match file.write_all(front).and_then(|_| file.write_all(back)) {
+                                    Ok(_) => {}
+                                    Err(e) => {
+                                        eprintln!("\nError writing log channel {}: {}", i, e);
+                                        continue;
                                     }
                                 }
-                                Err(e) => {
-                                    eprintln!(
-                                        "\nCould not create log file {}: {}",
-                                        final_path.display(),
-                                        e
+                                // Markers can't be part of the binary data, so they are saved
+                                // next to it with the offset of the data they precede.
+                                if tab.data_markers().next().is_some() {
+                                    let name = format!("{}_channel{}_markers.txt", self.logname, i);
+                                    let markers_path = path.join(sanitize_log_name(name));
+                                    let written = std::fs::File::create(&markers_path).and_then(
+                                        |mut file| {
+                                            for (offset, text) in tab.data_markers() {
+                                                writeln!(file, "{:#x} {}", offset, text)?;
+                                            }
+                                            file.flush()
+                                        },
                                     );
+                                    if let Err(e) = written {
+                                        eprintln!(
+                                            "\nError writing markers of log channel {}: {}",
+                                            i, e
+                                        );
+                                    }
                                 }
                             }
+                            DataFormat::Defmt => unreachable!(),
+                        };
+
+                        // Flush file
+                        if let Err(e) = file.flush() {
+                            eprintln!("Error writing log channel {}: {}", i, e)
                         }
                     }
-                    true
-                }
-                KeyCode::Char('l') if event.modifiers.contains(KeyModifiers::CONTROL) => {
-                    self.current_tab_mut().clear();
-                    false
-                }
-                KeyCode::F(n) => {
-                    let n = n as usize - 1;
-                    if n < self.tabs.len() {
-                        self.current_tab = n;
+                    Err(e) => {
+                        eprintln!(
+                            "\nCould not create log file {}: {}",
+                            final_path.display(),
+                            e
+                        );
                     }
-                    false
-                }
-                KeyCode::Enter => {
-                    self.push_rtt(core);
-                    false
-                }
-                KeyCode::Char(c) => {
-                    self.current_tab_mut().input_mut().push(c);
-                    false
                 }
-                KeyCode::Backspace => {
-                    self.current_tab_mut().input_mut().pop();
-                    false
+            }
+        }
+    }
+
+    /// Returns true if the application should exit.
+    fn handle_action(&mut self, action: Action, session: Option<&mut Session>) -> bool {
+        match action {
+            Action::Quit => {
+                self.quit();
+                true
+            }
+            Action::Clear => {
+                if self.current_tab().is_merged() {
+                    self.tabs.iter_mut().for_each(ChannelState::clear);
+                } else {
+                    self.current_tab_mut().clear();
                 }
-                KeyCode::PageUp => {
-                    self.current_tab_mut().scroll_up();
-                    false
+                false
+            }
+            Action::Split => {
+                self.cycle_split();
+                false
+            }
+            Action::NextPane => {
+                self.focus_next_pane();
+                false
+            }
+            Action::NextTab => {
+                self.select_tab((self.current_tab + 1) % self.tabs.len());
+                false
+            }
+            Action::PreviousTab => {
+                self.select_tab((self.current_tab + self.tabs.len() - 1) % self.tabs.len());
+                false
+            }
+            Action::TogglePlot => {
+                self.current_tab_mut().toggle_plot_view();
+                false
+            }
+            Action::ToggleHex => {
+                self.current_tab_mut().toggle_hex_input();
+                false
+            }
+            Action::SendFile => {
+                let tab = &mut self.tabs[self.current_tab];
+                let mut core = attach(session, tab.core());
+                tab.send_file(core.as_mut());
+                false
+            }
+            Action::ScrollUp => {
+                self.current_tab_mut().page_up();
+                false
+            }
+            Action::ScrollDown => {
+                self.current_tab_mut().page_down();
+                false
+            }
+            Action::ScrollLineUp => {
+                self.current_tab_mut().scroll_up(1);
+                false
+            }
+            Action::ScrollLineDown => {
+                self.current_tab_mut().scroll_down(1);
+                false
+            }
+            Action::ScrollToStart => {
+                self.current_tab_mut().scroll_to_start();
+                false
+            }
+            Action::ScrollToEnd => {
+                self.current_tab_mut().scroll_to_end();
+                false
+            }
+            Action::Marker => {
+                self.marker_prompt = true;
+                false
+            }
+            Action::PreviousMarker => {
+                self.current_tab_mut().jump_to_marker(false);
+                false
+            }
+            Action::NextMarker => {
+                self.current_tab_mut().jump_to_marker(true);
+                false
+            }
+            Action::Pause => {
+                self.current_tab_mut().toggle_pause();
+                false
+            }
+            Action::Help => {
+                self.show_help = true;
+                false
+            }
+        }
+    }
+
+    /// Handles a key while the annotation of a marker is entered.
+    fn handle_marker_key(&mut self, event: KeyEvent) {
+        let input = &mut self.marker_input;
+        match event.code {
+            KeyCode::Enter => {
+                let annotation = input.submit();
+                self.marker_prompt = false;
+                self.insert_marker(&annotation);
+            }
+            KeyCode::Esc => {
+                input.end();
+                input.delete_to_start();
+                self.marker_prompt = false;
+            }
+            KeyCode::Char(c)
+                if !event
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                input.insert(c)
+            }
+            KeyCode::Backspace => input.backspace(),
+            KeyCode::Delete => input.delete(),
+            KeyCode::Left => input.left(),
+            KeyCode::Right => input.right(),
+            KeyCode::Home => input.home(),
+            KeyCode::End => input.end(),
+            KeyCode::Up => input.history_previous(),
+            KeyCode::Down => input.history_next(),
+            _ => {}
+        }
+    }
+
+    /// Inserts a numbered marker line into all tabs.
+    fn insert_marker(&mut self, annotation: &str) {
+        self.markers += 1;
+        let mut text = format!(
+            "── Marker {} at {}",
+            self.markers,
+            Local::now().format("%H:%M:%S%.3f")
+        );
+        if !annotation.is_empty() {
+            text = format!("{}: {}", text, annotation);
+        }
+        for tab in &mut self.tabs {
+            tab.insert_marker(self.poll_round, text.clone());
+        }
+    }
+
+    /// Returns true if the application should exit.
+    fn handle_key(&mut self, event: KeyEvent, session: Option<&mut Session>) -> bool {
+        if self.show_help {
+            self.show_help = false;
+            return false;
+        }
+        if self.show_crash {
+            self.show_crash = false;
+            return false;
+        }
+        if self.marker_prompt {
+            self.handle_marker_key(event);
+            return false;
+        }
+
+        // In tabs with an input line, printable characters and Tab always belong to it, even if
+        // they are bound to an action, so every line can be typed and completed.
+        let has_input = self.current_tab().has_down_channel();
+        let typed = matches!(event.code, KeyCode::Char(_) | KeyCode::Tab)
+            && !event
+                .modifiers
+                .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+        if !(has_input && typed) {
+            if let Some(action) = self.keys.action(event) {
+                return self.handle_action(action, session);
+            }
+        }
+
+        // Tabs without an input line use its keys for scrolling.
+        if !self.current_tab().has_down_channel() {
+            let action = match event.code {
+                KeyCode::Up => Some(Action::ScrollLineUp),
+                KeyCode::Down => Some(Action::ScrollLineDown),
+                KeyCode::Home => Some(Action::ScrollToStart),
+                KeyCode::End => Some(Action::ScrollToEnd),
+                _ => None,
+            };
+            if let Some(action) = action {
+                return self.handle_action(action, session);
+            }
+        }
+
+        match event.code {
+            KeyCode::Char(c) if event.modifiers.contains(KeyModifiers::ALT) => {
+                // Alt+1 to Alt+9 select the first nine tabs, Alt+0 the tenth.
+                if let Some(n) = c.to_digit(10) {
+                    self.select_tab((n as usize + 9) % 10);
                 }
-                KeyCode::PageDown => {
-                    self.current_tab_mut().scroll_down();
-                    false
+                false
+            }
+            KeyCode::F(n) => {
+                self.select_tab(n as usize - 1);
+                false
+            }
+            KeyCode::Enter => {
+                self.push_rtt(session);
+                false
+            }
+            KeyCode::Char(c) if self.current_tab().plot_visible() => {
+                self.handle_plot_key(c);
+                false
+            }
+            KeyCode::Char(c) if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                let input = self.current_tab_mut().input_mut();
+                match c {
+                    'a' => input.home(),
+                    'e' => input.end(),
+                    'w' => input.delete_word(),
+                    'u' => input.delete_to_start(),
+                    _ => {}
                 }
-                _ => false,
-            },
-            Err(RecvTimeoutError::Disconnected) => {
-                log::warn!("Unable to receive anymore input events from terminal, shutting down.");
-                true
+                false
             }
-            // Timeout just means no input received.
-            Err(RecvTimeoutError::Timeout) => false,
+            KeyCode::Char(c) => {
+                self.current_tab_mut().input_mut().insert(c);
+                false
+            }
+            KeyCode::Backspace => {
+                self.current_tab_mut().input_mut().backspace();
+                false
+            }
+            KeyCode::Delete => {
+                self.current_tab_mut().input_mut().delete();
+                false
+            }
+            KeyCode::Left => {
+                self.current_tab_mut().input_mut().left();
+                false
+            }
+            KeyCode::Right => {
+                self.current_tab_mut().input_mut().right();
+                false
+            }
+            KeyCode::Home => {
+                self.current_tab_mut().input_mut().home();
+                false
+            }
+            KeyCode::End => {
+                self.current_tab_mut().input_mut().end();
+                false
+            }
+            KeyCode::Up => {
+                self.current_tab_mut().input_mut().history_previous();
+                false
+            }
+            KeyCode::Down => {
+                self.current_tab_mut().input_mut().history_next();
+                false
+            }
+            KeyCode::Tab => {
+                self.current_tab_mut().input_mut().complete();
+                false
+            }
+            _ => false,
         }
     }
 
@@ -480,19 +1040,725 @@ impl App {
         &mut self.tabs[self.current_tab]
     }
 
-    /// Polls the RTT target for new data on all channels.
-    pub fn poll_rtt(&mut self, core: &mut Core) {
+    /// Polls the RTT target for new data on all channels, each on the core it belongs to.
+    ///
+    /// Without a session, only channels which don't read from a target receive data.
+    pub fn poll_rtt(&mut self, mut session: Option<&mut Session>) {
+        self.poll_round += 1;
+        // Up channels are numbered in the recording in the order of the tabs.
+        let mut recorded = 0;
         for channel in self.tabs.iter_mut() {
-            channel.poll_rtt(core);
+            let mut core = attach(session.as_deref_mut(), channel.core());
+            let received = channel.poll_rtt(core.as_mut(), self.poll_round);
+            if let Some(recorder) = self.recorder.as_mut() {
+                for read in &received {
+                    if let Err(e) = recorder.record(recorded, read.time, &read.data) {
+                        log::warn!("Could not record RTT data, recording stopped: {}", e);
+                        self.recorder = None;
+                        break;
+                    }
+                }
+            }
+            if channel.has_up_channel() {
+                recorded += 1;
+            }
+            channel.write_pending(core.as_mut());
+        }
+        self.merge_lines();
+
+        let elapsed = self.last_status_update.elapsed();
+        if elapsed >= STATUS_INTERVAL {
+            self.last_status_update = Instant::now();
+            if let Some(mut core) = attach(session, self.current_tab().core()) {
+                self.core_status = core.status().ok();
+            }
+            for tab in &mut self.tabs {
+                tab.update_stats(elapsed);
+            }
+            self.flush_recording();
+        }
+    }
+
+    fn flush_recording(&mut self) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            if let Err(e) = recorder.flush() {
+                log::warn!("Could not write the recording: {}", e);
+            }
+        }
+    }
+
+    /// Copies the lines received on all channels to the merged tab, if there is one.
+    fn merge_lines(&mut self) {
+        let merged = match self.tabs.iter().position(ChannelState::is_merged) {
+            Some(merged) => merged,
+            None => return,
+        };
+
+        let mut lines = Vec::new();
+        for (i, tab) in self.tabs.iter_mut().enumerate() {
+            if tab.is_merged() {
+                continue;
+            }
+            // Markers are inserted into the merged tab directly.
+            lines.extend(
+                tab.new_lines()
+                    .into_iter()
+                    .filter(|line| !line.marker)
+                    .map(|line| Line {
+                        tab: Some(i),
+                        ..line
+                    }),
+            );
+        }
+
+        // Lines of the same round stay ordered like the tabs, as that is the order they were
+        // polled in.
+        lines.sort_by_key(|line| line.round);
+        for line in lines {
+            self.tabs[merged].push_line(line);
         }
     }
 
-    pub fn push_rtt(&mut self, core: &mut Core) {
-        self.tabs[self.current_tab].push_rtt(core);
+    pub fn push_rtt(&mut self, session: Option<&mut Session>) {
+        let tab = &mut self.tabs[self.current_tab];
+        let mut core = attach(session, tab.core());
+        tab.push_rtt(core.as_mut());
+    }
+}
+
+/// Returns core `n` of `session`, or `None` without a session.
+fn attach(session: Option<&mut Session>, n: usize) -> Option<Core<'_>> {
+    match session?.core(n) {
+        Ok(core) => Some(core),
+        Err(e) => {
+            log::warn!("Could not attach to core {}: {}", n, e);
+            None
+        }
     }
 }
 
+/// How a channel is placed on the screen.
+#[derive(Debug, Clone, Copy)]
+struct Pane {
+    /// Whether the channel is drawn with a border and its name as title.
+    bordered: bool,
+    /// Whether the channel receives the keyboard input.
+    focused: bool,
+}
+
+/// Renders `tab` into `area`.
+///
+/// `prefixes` holds the prefix of the lines of every tab in the merged tab.
+/// Returns the number of lines and the height available to them, if the messages were shown.
+fn render_channel<B: Backend>(
+    f: &mut Frame<B>,
+    tab: &mut ChannelState,
+    prefixes: &[String],
+    area: Rect,
+    pane: Pane,
+    theme: &Theme,
+) -> Option<(usize, usize)> {
+    let area = if pane.bordered {
+        let border_style = if pane.focused {
+            theme.border
+        } else {
+            Style::default()
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(border_style)
+            .title(tab.name());
+        let inner = block.inner(area);
+        f.render_widget(block, area);
+        inner
+    } else {
+        area
+    };
+
+    if tab.plot_visible() {
+        if let Some(plot) = tab.plot() {
+            render_plot(f, plot, area, theme);
+        }
+        return None;
+    }
+
+    // Tells that the view does not follow new lines, either because it is paused or scrolled up.
+    let info = match tab.paused_lines() {
+        Some(lines) => Some((format!("Paused, {} new lines", lines), theme.notice)),
+        None if tab.scroll_offset() > 0 => Some((
+            format!(
+                "{} rows below, scroll to the bottom to follow new lines",
+                tab.scroll_offset()
+            ),
+            theme.dim,
+        )),
+        None => None,
+    };
+    let mut constraints = vec![Constraint::Min(1)];
+    if info.is_some() {
+        constraints.push(Constraint::Length(1));
+    }
+    if tab.has_down_channel() {
+        constraints.push(Constraint::Length(1));
+    }
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(0)
+        .constraints(constraints)
+        .split(area);
+
+    let height = chunks[0].height as usize;
+    let width = chunks[0].width as usize;
+    let indent = |line: &Line| line.tab.map_or(0, |i| prefixes[i].chars().count());
+    // The rows received while paused are hidden below the view.
+    let rows = tab.scrollback_mut().wrap(width, indent) - tab.paused_rows();
+    tab.set_view_height(height);
+    let dropped = tab.scrollback().dropped();
+    let message_num = rows + usize::from(dropped > 0);
+    let scroll_offset = tab.scroll_offset();
+
+    // The dropped lines are indicated above the oldest line, once it is scrolled into view.
+    let indicator = dropped > 0 && scroll_offset + height >= message_num;
+    let mut messages = Vec::new();
+    if indicator {
+        messages.push(ListItem::new(Span::styled(
+            format!("... {} lines dropped", dropped),
+            theme.dim,
+        )));
+    }
+    for row in tab.scrollback().rows(
+        scroll_offset + tab.paused_rows(),
+        height.saturating_sub(usize::from(indicator)),
+    ) {
+        let spans = match row.line.tab {
+            Some(i) => {
+                let prefix = if row.first {
+                    prefixes[i].clone()
+                } else {
+                    " ".repeat(indent(row.line))
+                };
+                Spans::from(vec![
+                    Span::styled(prefix, Style::default().fg(theme.color(i))),
+                    Span::raw(row.text.to_owned()),
+                ])
+            }
+            None if row.line.marker => Spans::from(Span::styled(row.text.to_owned(), theme.marker)),
+            None => Spans::from(row.text.to_owned()),
+        };
+        messages.push(ListItem::new(spans));
+    }
+
+    let messages = List::new(messages.as_slice()).block(Block::default().borders(Borders::NONE));
+    f.render_widget(messages, chunks[0]);
+
+    if let Some((info, style)) = &info {
+        let info = Paragraph::new(Spans::from(vec![Span::raw(info.as_str())])).style(*style);
+        f.render_widget(info, chunks[1]);
+    }
+
+    let chunks = &chunks[usize::from(info.is_some())..];
+    if tab.has_down_channel() {
+        let input_style = if pane.focused {
+            theme.input
+        } else {
+            theme.inactive_input
+        };
+        if let Some(progress) = tab.sending_progress() {
+            let input = Paragraph::new(Spans::from(vec![Span::raw(progress)])).style(input_style);
+            f.render_widget(input, chunks[1]);
+        } else if let Some(notice) = tab.notice() {
+            let input = Paragraph::new(Spans::from(vec![Span::raw(notice)])).style(theme.notice);
+            f.render_widget(input, chunks[1]);
+        } else {
+            let prefix = if tab.hex_input() { "[hex] " } else { "" };
+            let width = chunks[1].width as usize;
+            let (line, cursor) = tab.input().view(width.saturating_sub(prefix.len()));
+            let input = Paragraph::new(Spans::from(vec![
+                Span::styled(prefix, input_style.add_modifier(Modifier::BOLD)),
+                Span::raw(line),
+            ]))
+            .style(input_style);
+            f.render_widget(input, chunks[1]);
+
+            if pane.focused {
+                let x = chunks[1].x + (prefix.len() + cursor).min(width) as u16;
+                f.set_cursor(x, chunks[1].y);
+            }
+        }
+    }
+
+    Some((message_num, height))
+}
+
+/// Renders the plot view of a channel into `area`.
+fn render_plot<B: Backend>(f: &mut Frame<B>, plot: &PlotState, area: Rect, theme: &Theme) {
+    let x_bounds = plot.bounds();
+    let series = plot.visible(x_bounds);
+
+    // Scale the y axis to the visible samples.
+    let (min, max) = series
+        .iter()
+        .flat_map(|s| s.points.iter().map(|&(_, value)| value))
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+            (min.min(value), max.max(value))
+        });
+    let y_bounds = if min > max {
+        [0.0, 1.0]
+    } else if min == max {
+        [min - 1.0, max + 1.0]
+    } else {
+        let margin = (max - min) * 0.05;
+        [min - margin, max + margin]
+    };
+
+    let mut status = format!(" Window {}s", plot.window());
+    if plot.is_paused() {
+        status += " [paused]";
+    }
+    status += " | +/- window, space pause, s export CSV, Ctrl+P log view";
+    if let Some(message) = plot.status() {
+        status += " | ";
+        status += message;
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(0)
+        .constraints([Constraint::Min(1), Constraint::Length(1)].as_ref())
+        .split(area);
+
+    let datasets = series
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            Dataset::default()
+                .name(s.name.as_str())
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(theme.color(i)))
+                .data(&s.points)
+        })
+        .collect();
+
+    let chart = Chart::new(datasets)
+        .block(Block::default().borders(Borders::NONE))
+        .x_axis(Axis::default().bounds(x_bounds).labels(vec![
+            Span::raw(format!("{:.1}s", x_bounds[0])),
+            Span::raw(format!("{:.1}s", x_bounds[1])),
+        ]))
+        .y_axis(Axis::default().bounds(y_bounds).labels(vec![
+            Span::raw(format!("{:.2}", y_bounds[0])),
+            Span::raw(format!("{:.2}", (y_bounds[0] + y_bounds[1]) / 2.0)),
+            Span::raw(format!("{:.2}", y_bounds[1])),
+        ]));
+    f.render_widget(chart, chunks[0]);
+
+    let status = Paragraph::new(Spans::from(vec![Span::raw(status)])).style(theme.input);
+    f.render_widget(status, chunks[1]);
+}
+
+/// Shows the key bindings in a box in the middle of the screen.
+fn render_help<B: Backend>(f: &mut Frame<B>, help: &[(String, &str)]) {
+    let keys_width = help.iter().map(|(keys, _)| keys.len()).max().unwrap_or(0);
+    let lines = help
+        .iter()
+        .map(|(keys, description)| {
+            ListItem::new(Spans::from(vec![
+                Span::styled(
+                    format!(" {:width$}  ", keys, width = keys_width),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(*description),
+            ]))
+        })
+        .collect::<Vec<_>>();
+
+    let size = f.size();
+    let width = (keys_width as u16 + 64).min(size.width);
+    let height = (lines.len() as u16 + 2).min(size.height);
+    let area = Rect::new(
+        (size.width - width) / 2,
+        (size.height - height) / 2,
+        width,
+        height,
+    );
+
+    let help = List::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Keys, press any key to close "),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(help, area);
+}
+
+fn render_crash<B: Backend>(f: &mut Frame<B>, crash: &Crash) {
+    let lines = crash
+        .lines
+        .iter()
+        .map(|line| ListItem::new(Spans::from(format!(" {}", line))))
+        .collect::<Vec<_>>();
+
+    let size = f.size();
+    let width = (crash.lines.iter().map(|line| line.len()).max().unwrap_or(0) as u16 + 4)
+        .max(crash.title.len() as u16 + 36)
+        .min(size.width);
+    let height = (lines.len() as u16 + 2).min(size.height);
+    let area = Rect::new(
+        (size.width - width) / 2,
+        (size.height - height) / 2,
+        width,
+        height,
+    );
+
+    let crash = List::new(lines).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" {}, press any key to close ", crash.title)),
+    );
+    f.render_widget(Clear, area);
+    f.render_widget(crash, area);
+}
+
+/// Applies the options which are set for all channels and can be overridden per channel.
+fn apply_channel_options(
+    tab: &mut ChannelState,
+    channel: Option<&ChannelConfig>,
+    config: &crate::config::Rtt,
+    start: DateTime<Local>,
+) {
+    tab.set_scrollback_limit(
+        channel
+            .and_then(|channel| channel.scrollback)
+            .unwrap_or(config.scrollback),
+    );
+    tab.set_timestamps(
+        channel
+            .and_then(|channel| channel.timestamps.clone())
+            .unwrap_or_else(|| config.timestamps.clone()),
+        start,
+    );
+}
+
+/// Code farther down relies on tabs being configured and might panic otherwise.
+fn check_tabs(tabs: &[ChannelState]) -> Result<()> {
+    if tabs.is_empty() {
+        return Err(anyhow!(
+            "Failed to initialize RTT UI: No RTT channels configured"
+        ));
+    }
+    Ok(())
+}
+
+/// Replaces characters which are not allowed in file names.
+fn sanitize_log_name(name: String) -> String {
+    let sanitize_options = sanitize_filename::Options {
+        replacement: "_",
+        ..Default::default()
+    };
+    sanitize_filename::sanitize_with_options(name, sanitize_options)
+}
+
+/// The number of lines scrolled per step of the mouse wheel.
+const SCROLL_LINES: usize = 3;
+
+fn contains(area: &Rect, column: u16, row: u16) -> bool {
+    column >= area.left() && column < area.right() && row >= area.top() && row < area.bottom()
+}
+
 pub fn clean_up_terminal() {
     let _ = disable_raw_mode();
-    let _ = execute!(std::io::stdout(), LeaveAlternateScreen);
+    let _ = execute!(std::io::stdout(), DisableMouseCapture, LeaveAlternateScreen);
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc::{self, Receiver, Sender};
+
+    use crossterm::event::{Event as CEvent, KeyCode, KeyEvent, KeyModifiers};
+    use tui::{backend::TestBackend, Terminal};
+
+    use super::App;
+    use crate::{
+        config::{Config, Configs},
+        rttui::{
+            channel::{test::defmt_state, ChannelState, DataFormat},
+            layout::SplitLayout,
+            source::MemoryChannel,
+            status::SessionInfo,
+            theme::Theme,
+            timestamp::{DefmtTimestamp, TimestampConfig},
+        },
+    };
+
+    type TestApp = App<TestBackend, Receiver<CEvent>>;
+
+    fn config() -> Config {
+        let mut config = Configs::defaults().select_defined("default").unwrap();
+        config.rtt.show_timestamps = false;
+        config.rtt.log_enabled = false;
+        config.rtt.input_history = false;
+        config
+    }
+
+    fn app(tabs: Vec<ChannelState>, width: u16, height: u16) -> (TestApp, Sender<CEvent>) {
+        app_with_config(tabs, &config(), width, height)
+    }
+
+    fn app_with_config(
+        tabs: Vec<ChannelState>,
+        config: &Config,
+        width: u16,
+        height: u16,
+    ) -> (TestApp, Sender<CEvent>) {
+        let terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        let (events, rx) = mpsc::channel();
+        let mut app = App::with_terminal(
+            tabs,
+            config,
+            "test".to_owned(),
+            SessionInfo::default(),
+            terminal,
+            rx,
+        )
+        .unwrap();
+        // The colors don't depend on NO_COLOR of the environment the tests run in.
+        app.theme = Theme::new(&config.rtt.theme, false);
+        (app, events)
+    }
+
+    fn key(events: &Sender<CEvent>, code: KeyCode) {
+        let event = KeyEvent::new(code, KeyModifiers::NONE);
+        events.send(CEvent::Key(event)).unwrap();
+    }
+
+    /// Renders the UI and returns the text on the screen, one string per row.
+    fn screen(app: &mut TestApp) -> Vec<String> {
+        app.render();
+        let buffer = app.terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer.get(x, y).symbol.as_str())
+                    .collect::<String>()
+                    .trim_end()
+                    .to_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn string_tab() {
+        let target = MemoryChannel::new(Some("Terminal".to_owned()), 16);
+        let tab = ChannelState::new(
+            Some(Box::new(target.clone())),
+            Some(Box::new(target.clone())),
+            None,
+            false,
+            DataFormat::String,
+            None,
+        );
+        let (mut app, events) = app(vec![tab], 20, 7);
+
+        target.push(b"first\nsecond line, which wraps\nthi");
+        app.poll_rtt(None);
+        assert_eq!(
+            screen(&mut app),
+            [
+                " Terminal",
+                "first",
+                "second line, which",
+                "wraps",
+                "thi",
+                "",
+                "  |   0 kHz | Core u",
+            ]
+        );
+
+        for c in "ok".chars() {
+            key(&events, KeyCode::Char(c));
+        }
+        key(&events, KeyCode::Enter);
+        for _ in 0..3 {
+            assert!(!app.handle_event(None));
+        }
+        assert_eq!(target.written(), b"ok\n");
+
+        // A view scrolled up stays in place when new lines arrive.
+        target.push(b"rd\nfourth\nfifth\n");
+        app.poll_rtt(None);
+        app.render();
+        key(&events, KeyCode::PageUp);
+        assert!(!app.handle_event(None));
+        app.render();
+        target.push(b"sixth\n");
+        app.poll_rtt(None);
+        assert_eq!(
+            screen(&mut app),
+            [
+                " Terminal",
+                "first",
+                "second line, which",
+                "wraps",
+                "4 rows below, scroll",
+                "",
+                "  |   0 kHz | Core u",
+            ]
+        );
+    }
+
+    #[test]
+    fn typed_keys_go_to_input_line() {
+        let target = MemoryChannel::new(Some("Terminal".to_owned()), 16);
+        let tabs = vec![
+            ChannelState::new(
+                None,
+                Some(Box::new(target)),
+                None,
+                false,
+                DataFormat::String,
+                None,
+            ),
+            ChannelState::new(
+                None,
+                None,
+                Some("Log".to_owned()),
+                false,
+                DataFormat::String,
+                None,
+            ),
+        ];
+        let (mut app, events) = app(tabs, 20, 5);
+
+        // Tab and "?" are bound, but an input line takes them even when it is empty.
+        for code in [KeyCode::Char('?'), KeyCode::Tab] {
+            key(&events, code);
+            app.handle_event(None);
+        }
+        assert_eq!(app.current_tab, 0);
+        assert!(!app.show_help);
+        assert_eq!(app.tabs[0].input().as_str(), "?");
+
+        app.select_tab(1);
+        key(&events, KeyCode::Char('?'));
+        app.handle_event(None);
+        assert!(app.show_help);
+    }
+
+    #[test]
+    fn split_panes() {
+        let tabs = ["one", "two", "three"]
+            .iter()
+            .map(|name| {
+                let target = MemoryChannel::new(Some(name.to_string()), 16);
+                target.push(format!("{} says hi\n", name).as_bytes());
+                ChannelState::new(
+                    Some(Box::new(target)),
+                    None,
+                    None,
+                    false,
+                    DataFormat::String,
+                    None,
+                )
+            })
+            .collect();
+        let mut config = config();
+        config.rtt.split = SplitLayout::Vertical;
+        config.rtt.split_tabs = vec![2, 9, 0];
+        let (mut app, _events) = app_with_config(tabs, &config, 20, 9);
+        app.poll_rtt(None);
+        assert_eq!(app.panes, vec![2, 0]);
+        assert_eq!(app.current_tab, 2);
+
+        assert_eq!(
+            screen(&mut app),
+            [
+                " one │ two │ three │",
+                "┌three─────────────┐",
+                "│three says hi     │",
+                "└──────────────────┘",
+                "┌one───────────────┐",
+                "│one says hi       │",
+                "│                  │",
+                "└──────────────────┘",
+                "  |   0 kHz | Core u",
+            ]
+        );
+
+        // Selecting a hidden tab replaces the focused pane.
+        app.focus_next_pane();
+        assert_eq!(app.current_tab, 0);
+        app.select_tab(1);
+        assert_eq!(app.panes, vec![2, 1]);
+
+        app.cycle_split();
+        assert_eq!(app.visible_panes(), vec![1]);
+        app.cycle_split();
+        assert_eq!(app.visible_panes(), vec![2, 1]);
+    }
+
+    #[test]
+    fn binary_tab() {
+        let target = MemoryChannel::new(Some("Binary".to_owned()), 1024);
+        let tab = ChannelState::new(
+            Some(Box::new(target.clone())),
+            None,
+            None,
+            false,
+            DataFormat::BinaryLE,
+            None,
+        );
+        let (mut app, _events) = app(vec![tab], 30, 5);
+
+        target.push(&[0x01, 0x02, 0xff]);
+        target.push(&[0x10]);
+        app.poll_rtt(None);
+        assert_eq!(
+            screen(&mut app),
+            [
+                " Binary",
+                "0x01, 0x02, 0xff,",
+                "0x10,",
+                "",
+                "  |   0 kHz | Core unknown | L",
+            ]
+        );
+    }
+
+    #[test]
+    fn defmt_tab() {
+        let target = MemoryChannel::new(None, 1024);
+        let mut tab = ChannelState::new(
+            Some(Box::new(target.clone())),
+            None,
+            Some("defmt".to_owned()),
+            false,
+            DataFormat::Defmt,
+            None,
+        );
+        tab.set_timestamps(
+            TimestampConfig {
+                defmt: DefmtTimestamp::Host,
+                ..TimestampConfig::default()
+            },
+            chrono::Local::now(),
+        );
+        let (mut app, _events) = app(vec![tab], 30, 5);
+        app.set_defmt(0, defmt_state());
+
+        // The frame of `Hello`, split across two reads.
+        target.push(&[0x01]);
+        target.push(&[0x00]);
+        app.poll_rtt(None);
+        assert_eq!(
+            screen(&mut app),
+            [
+                " defmt",
+                "INFO  Hello",
+                "└─ src/main.rs:12",
+                "",
+                "  |   0 kHz | Core unknown | L",
+            ]
+        );
+    }
 }
@@ -1,8 +1,28 @@
-use std::fmt;
+use std::{
+    collections::VecDeque,
+    fmt::{self, write},
+    fs,
+    io::Read,
+    sync::Arc,
+    time::Duration,
+};
 
-use chrono::Local;
+use chrono::{DateTime, Local};
+use defmt_decoder::{DecodeError, StreamDecoder};
 use probe_rs::Core;
-use probe_rs_rtt::{ChannelMode, DownChannel, UpChannel};
+use probe_rs_rtt::ChannelMode;
+
+use crate::symbols::Symbols;
+
+use super::{
+    input::LineEditor,
+    plot::{PlotConfig, PlotState, SampleFormat},
+    recording::RecordedChannel,
+    scrollback::{Line, Scrollback, ScrollbackLimit},
+    source::{DownSink, Received, Target, UpSource},
+    status::ChannelStats,
+    timestamp::{DefmtTimestamp, TimestampConfig, Timestamper},
+};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum DataFormat {
@@ -18,36 +38,104 @@ pub struct ChannelConfig {
     pub name: Option<String>,
     pub up_mode: Option<ChannelMode>,
     pub format: DataFormat,
+    pub plot: Option<PlotConfig>,
+    /// Commands offered for tab completion in the input line.
+    #[serde(default)]
+    pub commands: Vec<String>,
+    /// What is appended to each line sent to the down channel.
+    #[serde(default)]
+    pub line_ending: LineEnding,
+    /// How much history is kept, overrides `rtt.scrollback` for this channel.
+    pub scrollback: Option<ScrollbackLimit>,
+    /// How lines are timestamped, overrides `rtt.timestamps` for this channel.
+    pub timestamps: Option<TimestampConfig>,
+    /// The core whose RTT control block has the channel, or any attached core if not set.
+    pub core: Option<usize>,
+}
+
+/// What is appended to each line sent to a down channel.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum LineEnding {
+    None,
+    #[default]
+    #[serde(rename = "LF")]
+    Lf,
+    #[serde(rename = "CRLF")]
+    CrLf,
+}
+
+impl LineEnding {
+    fn as_bytes(self) -> &'static [u8] {
+        match self {
+            LineEnding::None => b"",
+            LineEnding::Lf => b"\n",
+            LineEnding::CrLf => b"\r\n",
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct ChannelState {
-    up_channel: Option<UpChannel>,
-    down_channel: Option<DownChannel>,
+    up_channel: Option<Box<dyn UpSource>>,
+    down_channel: Option<Box<dyn DownSink>>,
     name: String,
     format: DataFormat,
-    /// Contains the strings when [ChannelState::format] is [DataFormat::String].
-    messages: Vec<String>,
-    /// When [ChannelState::format] is not [DataFormat::String] this
-    /// contains RTT binary data or binary data in defmt format.
-    data: Vec<u8>,
+    /// The received lines, decoded according to [ChannelState::format].
+    scrollback: Scrollback,
+    /// Contains the RTT binary data when [ChannelState::format] is [DataFormat::BinaryLE],
+    /// limited to the same number of bytes as the scrollback.
+    data: VecDeque<u8>,
+    /// The number of bytes dropped from the front of `data`.
+    data_dropped: usize,
+    /// The markers inserted into the binary data, with the offset of the data they precede,
+    /// counted from the first byte received.
+    data_markers: Vec<(usize, String)>,
+    /// Decodes the frames of a defmt channel.
+    defmt: Option<DefmtDecoder>,
     last_line_done: bool,
-    input: String,
+    input: LineEditor,
     scroll_offset: usize,
-    rtt_buffer: RttBuffer,
+    /// The number of rows the history was shown with at the last render.
+    view_height: usize,
+    /// While the view is paused, the number of lines received before, including dropped ones.
+    paused: Option<usize>,
     show_timestamps: bool,
+    timestamps: Timestamper,
+    /// The numeric samples of this channel, if plotting is configured for it.
+    plot: Option<PlotState>,
+    /// Whether the plot view is shown instead of the messages.
+    show_plot: bool,
+    /// The number of lines already handed out by [ChannelState::new_lines].
+    forwarded: usize,
+    /// Whether this tab shows the messages of all other tabs instead of its own.
+    merged: bool,
+    line_ending: LineEnding,
+    /// Whether the input line is sent as hex encoded bytes.
+    hex_input: bool,
+    /// Typed data waiting to be written to the down channel once it has room.
+    pending: Vec<u8>,
+    /// The file being sent to the down channel. Typed data is held back until it is sent.
+    sending_file: Option<FileTransfer>,
+    /// A message about the down channel shown in place of the input line, e.g. an error.
+    notice: Option<String>,
+    stats: ChannelStats,
+    /// Used to annotate the addresses in received lines, if enabled.
+    symbols: Option<Arc<Symbols>>,
+    /// The core the channels are read from and written to.
+    core: usize,
 }
 
 impl ChannelState {
     pub fn new(
-        up_channel: Option<UpChannel>,
-        down_channel: Option<DownChannel>,
+        up_channel: Option<Box<dyn UpSource>>,
+        down_channel: Option<Box<dyn DownSink>>,
         name: Option<String>,
         show_timestamps: bool,
         format: DataFormat,
+        plot: Option<PlotConfig>,
     ) -> Self {
         let name = name
-            .or_else(|| up_channel.as_ref().and_then(|up| up.name().map(Into::into)))
+            .or_else(|| up_channel.as_ref().and_then(|up| up.name()).map(Into::into))
             .or_else(|| {
                 down_channel
                     .as_ref()
@@ -55,55 +143,263 @@ impl ChannelState {
             })
             .unwrap_or_else(|| "Unnamed channel".to_owned());
 
+        let plot = plot.filter(|plot| {
+            let accepted = plot.samples.accepts(format);
+            if !accepted {
+                log::warn!(
+                    "Samples of channel {} can't be plotted, {:?} samples need a {} channel.",
+                    name,
+                    plot.samples,
+                    if plot.samples == SampleFormat::KeyValue {
+                        "String"
+                    } else {
+                        "BinaryLE"
+                    }
+                );
+            }
+            accepted
+        });
+
         Self {
             up_channel,
             down_channel,
             name,
             format,
-            messages: Vec::new(),
+            scrollback: Scrollback::default(),
             last_line_done: true,
-            input: String::new(),
+            input: LineEditor::new(),
             scroll_offset: 0,
-            rtt_buffer: RttBuffer([0u8; 1024]),
+            view_height: 0,
+            paused: None,
             show_timestamps,
-            data: Vec::new(),
+            timestamps: Timestamper::new(TimestampConfig::default(), Local::now()),
+            data: VecDeque::new(),
+            data_dropped: 0,
+            data_markers: Vec::new(),
+            defmt: None,
+            plot: plot.map(PlotState::new),
+            show_plot: false,
+            forwarded: 0,
+            merged: false,
+            line_ending: LineEnding::default(),
+            hex_input: false,
+            pending: Vec::new(),
+            sending_file: None,
+            notice: None,
+            stats: ChannelStats::default(),
+            symbols: None,
+            core: 0,
         }
     }
 
+    /// Creates a tab which shows the messages of all channels interleaved in receive order.
+    pub fn new_merged(name: String, show_timestamps: bool) -> Self {
+        Self {
+            merged: true,
+            ..Self::new(
+                None,
+                None,
+                Some(name),
+                show_timestamps,
+                DataFormat::String,
+                None,
+            )
+        }
+    }
+
+    /// Returns true if this tab shows the messages of all other tabs.
+    pub fn is_merged(&self) -> bool {
+        self.merged
+    }
+
+    pub fn has_up_channel(&self) -> bool {
+        self.up_channel.is_some()
+    }
+
+    /// Describes the up channel of this tab for a recording.
+    pub fn recorded_channel(&self) -> Option<RecordedChannel> {
+        let up_channel = self.up_channel.as_ref()?;
+        Some(RecordedChannel {
+            name: self.name.clone(),
+            format: self.format,
+            buffer_size: up_channel.buffer_size(),
+            plot: self.plot.as_ref().map(|plot| plot.config().clone()),
+            core: self.core,
+        })
+    }
+
     pub fn has_down_channel(&self) -> bool {
         self.down_channel.is_some()
     }
 
-    pub fn messages(&self) -> &Vec<String> {
-        &self.messages
+    pub fn scrollback(&self) -> &Scrollback {
+        &self.scrollback
+    }
+
+    pub fn scrollback_mut(&mut self) -> &mut Scrollback {
+        &mut self.scrollback
+    }
+
+    pub fn set_scrollback_limit(&mut self, limit: ScrollbackLimit) {
+        self.scrollback.set_limit(limit);
+    }
+
+    /// Sets how lines are timestamped. Relative timestamps count from `start`.
+    pub fn set_timestamps(&mut self, config: TimestampConfig, start: DateTime<Local>) {
+        self.timestamps = Timestamper::new(config, start);
+    }
+
+    /// Decodes the frames received on a defmt channel with `state`.
+    pub fn set_defmt(&mut self, state: &'static DefmtState) {
+        self.defmt = Some(DefmtDecoder {
+            state,
+            stream: state.0.new_stream_decoder(),
+            malformed: false,
+        });
+    }
+
+    /// Annotates the addresses of functions in received lines with their symbols.
+    pub fn set_symbols(&mut self, symbols: Arc<Symbols>) {
+        self.symbols = Some(symbols);
     }
 
-    pub fn input(&self) -> &str {
+    pub fn input(&self) -> &LineEditor {
         &self.input
     }
 
-    pub fn input_mut(&mut self) -> &mut String {
+    pub fn input_mut(&mut self) -> &mut LineEditor {
         &mut self.input
     }
 
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.line_ending = line_ending;
+    }
+
+    pub fn hex_input(&self) -> bool {
+        self.hex_input
+    }
+
+    /// Switches between sending the input line as text and as hex encoded bytes.
+    pub fn toggle_hex_input(&mut self) {
+        self.hex_input = !self.hex_input;
+    }
+
+    pub fn notice(&self) -> Option<&str> {
+        self.notice.as_deref()
+    }
+
+    pub fn clear_notice(&mut self) {
+        self.notice = None;
+    }
+
+    /// Returns the progress of the file being sent to the down channel.
+    pub fn sending_progress(&self) -> Option<String> {
+        self.sending_file
+            .as_ref()
+            .map(|file| format!("Sending {}: {}/{} bytes", file.name, file.sent, file.size))
+    }
+
     pub fn scroll_offset(&self) -> usize {
         self.scroll_offset
     }
 
-    pub fn scroll_up(&mut self) {
-        self.scroll_offset += 1;
+    /// Scrolls up by `rows`. The offset is limited to the history when it is rendered.
+    pub fn scroll_up(&mut self, rows: usize) {
+        self.scroll_offset += rows;
     }
 
-    pub fn scroll_down(&mut self) {
-        if self.scroll_offset > 0 {
-            self.scroll_offset -= 1;
+    /// Scrolls down by `rows`. New lines are followed again once the bottom is reached.
+    pub fn scroll_down(&mut self, rows: usize) {
+        self.scroll_offset = self.scroll_offset.saturating_sub(rows);
+    }
+
+    /// Scrolls up by the height of the view.
+    pub fn page_up(&mut self) {
+        self.scroll_up(self.view_height.max(1));
+    }
+
+    /// Scrolls down by the height of the view.
+    pub fn page_down(&mut self) {
+        self.scroll_down(self.view_height.max(1));
+    }
+
+    pub fn scroll_to_start(&mut self) {
+        self.scroll_offset = self.scrollback.rows_from(0);
+    }
+
+    /// Scrolls to the newest line and follows new lines from then on.
+    pub fn scroll_to_end(&mut self) {
+        self.scroll_offset = 0;
+    }
+
+    pub fn set_view_height(&mut self, height: usize) {
+        self.view_height = height;
+    }
+
+    /// Scrolls the next marker below the top of the view to the top, or the previous one above
+    /// it if `forward` is false.
+    pub fn jump_to_marker(&mut self, forward: bool) {
+        let current = self.scroll_offset;
+        let hidden = self.paused_rows();
+        let mut offsets = self.scrollback.markers().map(|i| {
+            self.scrollback
+                .rows_from(i)
+                .saturating_sub(hidden + self.view_height)
+        });
+        // Offsets decrease towards the newest line.
+        let target = if forward {
+            offsets.find(|&offset| offset < current)
+        } else {
+            offsets.filter(|&offset| offset > current).last()
+        };
+        if let Some(offset) = target {
+            self.scroll_offset = offset;
+        }
+    }
+
+    /// Adds a marker line to the history.
+    ///
+    /// An incomplete line received before stays above the marker and is continued by the next
+    /// data received.
+    pub fn insert_marker(&mut self, round: u64, text: String) {
+        if self.format == DataFormat::BinaryLE {
+            self.data_markers
+                .push((self.data_dropped + self.data.len(), text.clone()));
         }
+        self.push_line(Line {
+            round,
+            text,
+            tab: None,
+            marker: true,
+        });
+    }
+
+    pub fn stats(&self) -> &ChannelStats {
+        &self.stats
+    }
+
+    /// Computes the throughput and buffer statistics of the interval which ended after `elapsed`.
+    pub fn update_stats(&mut self, elapsed: Duration) {
+        let buffer_size = self.up_channel.as_ref().map_or(0, |up| up.buffer_size());
+        self.stats.update(elapsed, buffer_size);
     }
 
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    pub fn set_name(&mut self, name: String) {
+        self.name = name;
+    }
+
+    pub fn core(&self) -> usize {
+        self.core
+    }
+
+    pub fn set_core(&mut self, core: usize) {
+        self.core = core;
+    }
+
     pub fn format(&self) -> DataFormat {
         self.format
     }
@@ -114,47 +410,135 @@ impl ChannelState {
 
     pub fn clear(&mut self) {
         self.scroll_offset = 0;
-        self.data = Vec::new();
-        self.messages = Vec::new();
+        self.data = VecDeque::new();
+        self.data_dropped = 0;
+        self.data_markers.clear();
+        self.scrollback.clear();
+        self.forwarded = 0;
+        if self.paused.is_some() {
+            self.paused = Some(0);
+        }
+    }
+
+    /// Freezes the view while new lines keep being received, or shows them again.
+    pub fn toggle_pause(&mut self) {
+        self.paused = match self.paused {
+            Some(_) => None,
+            None => Some(self.scrollback.dropped() + self.scrollback.len()),
+        };
+    }
+
+    /// Returns the number of lines received since the view was paused, if it is.
+    pub fn paused_lines(&self) -> Option<usize> {
+        self.paused.map(|before| {
+            (self.scrollback.dropped() + self.scrollback.len()).saturating_sub(before)
+        })
+    }
+
+    /// Returns the number of rows hidden below the view because it is paused.
+    ///
+    /// Only counts rows of lines wrapped by the last call to [Scrollback::wrap].
+    pub fn paused_rows(&self) -> usize {
+        match self.paused {
+            Some(before) => self
+                .scrollback
+                .rows_from(before.saturating_sub(self.scrollback.dropped())),
+            None => 0,
+        }
     }
 
-    pub fn data(&self) -> &Vec<u8> {
+    pub fn data(&self) -> &VecDeque<u8> {
         &self.data
     }
 
+    /// Returns the markers inserted into the binary data, with the offset in [ChannelState::data]
+    /// of the data they precede. Markers of dropped data are skipped.
+    pub fn data_markers(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.data_markers
+            .iter()
+            .filter(move |(offset, _)| *offset >= self.data_dropped)
+            .map(move |(offset, text)| (offset - self.data_dropped, text.as_str()))
+    }
+
+    pub fn plot(&self) -> Option<&PlotState> {
+        self.plot.as_ref()
+    }
+
+    pub fn plot_mut(&mut self) -> Option<&mut PlotState> {
+        self.plot.as_mut()
+    }
+
+    /// Returns true if the plot view is shown instead of the messages.
+    pub fn plot_visible(&self) -> bool {
+        self.show_plot
+    }
+
+    /// Switches between the messages and the plot view, if plotting is configured.
+    pub fn toggle_plot_view(&mut self) {
+        self.show_plot = !self.show_plot && self.plot.is_some();
+    }
+
     /// Polls the RTT target for new data on the specified channel.
     ///
     /// Processes all the new data and adds it to the linebuffer of the respective channel.
-    pub fn poll_rtt(&mut self, core: &mut Core) {
+    /// `round` identifies the current polling round across all channels. Returns the received
+    /// data, so it can be recorded.
+    pub fn poll_rtt(&mut self, core: Option<&mut Core>, round: u64) -> Vec<Received> {
+        let up_channel = match self.up_channel.as_mut() {
+            Some(up_channel) => up_channel,
+            None => return Vec::new(),
+        };
+        let buffer_size = up_channel.buffer_size();
         // TODO: Proper error handling.
-        let count = if let Some(channel) = self.up_channel.as_mut() {
-            match channel.read(core, self.rtt_buffer.0.as_mut()) {
-                Ok(count) => count,
-                Err(err) => {
-                    log::error!("\nError reading from RTT: {}", err);
-                    return;
-                }
+        let received = match up_channel.read(&mut Target::new(core)) {
+            Ok(received) => received,
+            Err(err) => {
+                log::error!("\nError reading from RTT: {}", err);
+                return Vec::new();
             }
-        } else {
-            0
         };
 
-        if count == 0 {
-            return;
+        for read in &received {
+            self.stats.record(read.data.len(), buffer_size);
+            self.receive(&read.data, round, read.time);
+        }
+        received
+    }
+
+    /// Processes `data` received at `now`.
+    fn receive(&mut self, data: &[u8], round: u64, now: DateTime<Local>) {
+        if let Some(plot) = self.plot.as_mut() {
+            plot.ingest(data, now);
         }
 
+        let line = |text| Line {
+            round,
+            text,
+            tab: None,
+            marker: false,
+        };
+
         match self.format {
             DataFormat::String => {
-                let now = Local::now();
-
                 // First, convert the incoming bytes to UTF8.
-                let mut incoming = String::from_utf8_lossy(&self.rtt_buffer.0[..count]).to_string();
+                let mut incoming = String::from_utf8_lossy(data).to_string();
 
                 // Then pop the last stored line from our line buffer if possible and append our new line.
+                // A line which is continued by this read still counts as received by the earlier one.
                 let last_line_done = self.last_line_done;
+                let mut continued = None;
+                // Markers inserted while the line was incomplete are shown below it again.
+                let mut markers = Vec::new();
                 if !last_line_done {
-                    if let Some(last_line) = self.messages.pop() {
-                        incoming = last_line + &incoming;
+                    while let Some(mut last_line) = self.scrollback.pop_back() {
+                        if last_line.marker {
+                            markers.push(last_line);
+                            continue;
+                        }
+                        incoming = last_line.text + &incoming;
+                        last_line.text = String::new();
+                        continued = Some(last_line);
+                        break;
                     }
                 }
                 self.last_line_done = incoming.ends_with('\n');
@@ -165,38 +549,644 @@ impl ChannelState {
                 // you get a timestamp there too..
                 // Note: we timestamp at receipt of newline, not first char received if that
                 // matters.
-                for (i, line) in incoming.split_terminator('\n').enumerate() {
-                    if self.show_timestamps && (last_line_done || i > 0) {
-                        let ts = now.format("%H:%M:%S%.3f");
-                        self.messages.push(format!("{} {}", ts, line));
+                let texts = incoming.split_terminator('\n').collect::<Vec<_>>();
+                for (i, &text) in texts.iter().enumerate() {
+                    // Addresses may be split across reads, so only complete lines are annotated.
+                    let complete = i + 1 < texts.len() || self.last_line_done;
+                    let text = match &self.symbols {
+                        Some(symbols) if complete => symbols.annotate(text),
+                        _ => text.to_string(),
+                    };
+                    let text = if self.show_timestamps && (last_line_done || i > 0) {
+                        format!("{} {}", self.timestamps.host(now), text)
                     } else {
-                        self.messages.push(line.to_string());
+                        text
+                    };
+                    match continued.take() {
+                        Some(last_line) => self.push_line(Line { text, ..last_line }),
+                        None => self.push_line(line(text)),
+                    }
+                    while let Some(marker) = markers.pop() {
+                        self.push_line(marker);
                     }
-                    if self.scroll_offset != 0 {
-                        self.scroll_offset += 1;
+                }
+                while let Some(marker) = markers.pop() {
+                    self.push_line(marker);
+                }
+            }
+            DataFormat::BinaryLE => {
+                let bytes = data;
+                self.data.extend(bytes);
+                let excess = self.data.len().saturating_sub(self.scrollback_bytes());
+                self.data.drain(..excess);
+                self.data_dropped += excess;
+
+                let mut text = if self.show_timestamps {
+                    format!("{} ", self.timestamps.host(now))
+                } else {
+                    String::new()
+                };
+                for byte in bytes {
+                    let _ = write(&mut text, format_args!("{:#04x}, ", byte));
+                }
+                self.push_line(line(text));
+            }
+            DataFormat::Defmt => {
+                let decoder = self.defmt.as_mut().expect(
+                    "Running rtt in defmt mode but table or locations could not be loaded.",
+                );
+                let locs = &decoder.state.1;
+                // Incomplete frames are kept by the decoder until the rest arrives.
+                decoder.stream.received(data);
+
+                // All frames of a read share the host timestamp, the firmware's own timestamp is part
+                // of the frame.
+                let defmt_timestamp = self.timestamps.config().defmt;
+                let host_timestamp =
+                    if self.show_timestamps && defmt_timestamp != DefmtTimestamp::Target {
+                        Some(self.timestamps.host(now))
+                    } else {
+                        None
+                    };
+
+                let mut texts = Vec::new();
+                loop {
+                    match decoder.stream.decode() {
+                        Ok(frame) => {
+                            decoder.malformed = false;
+                            // NOTE(`[]` indexing) all indices in `table` have already been
+                            // verified to exist in the `locs` map.
+                            let loc = locs.as_ref().map(|locs| &locs[&frame.index()]);
+
+                            let mut text = match defmt_timestamp {
+                                DefmtTimestamp::Host => match frame.level() {
+                                    Some(level) => format!(
+                                        "{:5} {}",
+                                        level.as_str().to_uppercase(),
+                                        frame.display_message()
+                                    ),
+                                    None => frame.display_message().to_string(),
+                                },
+                                DefmtTimestamp::Target | DefmtTimestamp::Both => {
+                                    frame.display(false).to_string()
+                                }
+                            };
+                            if let Some(symbols) = &self.symbols {
+                                text = symbols.annotate(&text);
+                            }
+                            if let Some(timestamp) = &host_timestamp {
+                                text = format!("{} {}", timestamp, text);
+                            }
+                            texts.push(text);
+                            if let Some(loc) = loc {
+                                let relpath = if let Ok(relpath) =
+                                    loc.file.strip_prefix(std::env::current_dir().unwrap())
+                                {
+                                    relpath
+                                } else {
+                                    // not relative; use full path
+                                    &loc.file
+                                };
+
+                                texts.push(format!("└─ {}:{}", relpath.display(), loc.line));
+                            }
+                        }
+                        Err(DecodeError::UnexpectedEof) => break,
+                        Err(DecodeError::Malformed) => {
+                            if !decoder.malformed {
+                                log::warn!("Skipping malformed defmt data on {}.", self.name);
+                                decoder.malformed = true;
+                            }
+                            // The rzcobs decoder skips the malformed frame itself. The raw
+                            // decoder would fail on it again, so its data is dropped and decoding
+                            // starts over with the next read.
+                            if !decoder.state.0.encoding().can_recover() {
+                                decoder.stream = decoder.state.0.new_stream_decoder();
+                                break;
+                            }
+                        }
                     }
                 }
+
+                for text in texts {
+                    self.push_line(line(text));
+                }
             }
-            // defmt output is later formatted into strings in [App::render].
-            DataFormat::BinaryLE | DataFormat::Defmt => {
-                self.data.extend_from_slice(&self.rtt_buffer.0[..count]);
+        };
+    }
+
+    /// Adds a line to the history, keeping the view in place if it is scrolled up.
+    pub fn push_line(&mut self, line: Line) {
+        self.scrollback.push(line);
+        // A paused view is kept in place by hiding the new rows instead.
+        if self.scroll_offset != 0 && self.paused.is_none() {
+            self.scroll_offset += 1;
+        }
+    }
+
+    fn scrollback_bytes(&self) -> usize {
+        self.scrollback.limit().bytes
+    }
+
+    /// Returns the complete lines received since the last call, to show them in the merged tab.
+    pub fn new_lines(&mut self) -> Vec<Line> {
+        // An incomplete line is only handed out once it is complete, together with the markers
+        // inserted below it in the meantime.
+        let incomplete = if self.last_line_done {
+            0
+        } else {
+            self.scrollback
+                .lines()
+                .rev()
+                .take_while(|line| line.marker)
+                .count()
+                + 1
+        };
+        let complete = self.scrollback.len().saturating_sub(incomplete);
+        // Lines dropped before they were handed out are skipped.
+        let first = self.forwarded.saturating_sub(self.scrollback.dropped());
+        let lines = self
+            .scrollback
+            .lines()
+            .skip(first)
+            .take(complete.saturating_sub(first))
+            .cloned()
+            .collect();
+        self.forwarded = self.scrollback.dropped() + complete.max(first);
+        lines
+    }
+
+    /// Sends the input line to the down channel.
+    pub fn push_rtt(&mut self, core: Option<&mut Core>) {
+        if self.down_channel.is_none() {
+            return;
+        }
+
+        let bytes = if self.hex_input {
+            match parse_hex(self.input.as_str()) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    self.notice = Some(e);
+                    return;
+                }
             }
+        } else {
+            let mut bytes = self.input.as_str().as_bytes().to_vec();
+            bytes.extend_from_slice(self.line_ending.as_bytes());
+            bytes
         };
+        self.input.submit();
+
+        self.pending.extend_from_slice(&bytes);
+        self.write_pending(core);
+    }
+
+    /// Streams the file named in the input line to the down channel.
+    pub fn send_file(&mut self, core: Option<&mut Core>) {
+        if self.down_channel.is_none() || self.sending_file.is_some() {
+            return;
+        }
+
+        let path = self.input.as_str().trim().to_owned();
+        let file = fs::File::open(&path).and_then(|file| Ok((file.metadata()?.len(), file)));
+        match file {
+            Ok((size, file)) => {
+                self.input.submit();
+                self.sending_file = Some(FileTransfer {
+                    name: path,
+                    file,
+                    size,
+                    sent: 0,
+                    chunk: Vec::new(),
+                });
+                self.write_pending(core);
+            }
+            Err(e) => self.notice = Some(format!("Could not read {}: {}", path, e)),
+        }
     }
 
-    pub fn push_rtt(&mut self, core: &mut Core) {
-        if let Some(down_channel) = self.down_channel.as_mut() {
-            self.input += "\n";
-            down_channel.write(core, self.input.as_bytes()).unwrap();
-            self.input.clear();
+    /// Writes as much pending data to the down channel as currently fits into its buffer.
+    ///
+    /// The rest is kept until the next call, so large transfers don't overrun the target.
+    pub fn write_pending(&mut self, core: Option<&mut Core>) {
+        let down_channel = match self.down_channel.as_mut() {
+            Some(down_channel) => down_channel,
+            None => return,
+        };
+        let mut target = Target::new(core);
+
+        if let Some(file) = self.sending_file.as_mut() {
+            match file.write(down_channel.as_mut(), &mut target) {
+                Ok(true) => {
+                    self.notice = Some(format!("Sent {} ({} bytes)", file.name, file.sent));
+                    self.sending_file = None;
+                }
+                // Typed data is only sent after the file, so the two don't interleave.
+                Ok(false) => return,
+                Err(err) => {
+                    self.notice = Some(format!("Error sending {}: {}", file.name, err));
+                    self.sending_file = None;
+                    return;
+                }
+            }
         }
+
+        while !self.pending.is_empty() {
+            match down_channel.write(&mut target, &self.pending) {
+                // The buffer is full, the target has to read first.
+                Ok(0) => break,
+                Ok(count) => {
+                    self.pending.drain(..count);
+                }
+                Err(err) => {
+                    self.notice = Some(format!("Error writing to RTT: {}", err));
+                    self.pending.clear();
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// The defmt table of a firmware and, if complete, the locations of its log statements.
+pub type DefmtState = (defmt_decoder::Table, Option<defmt_decoder::Locations>);
+
+/// Decodes the defmt frames of a channel.
+struct DefmtDecoder {
+    state: &'static DefmtState,
+    /// Borrows the table in `state`, which is kept until the program exits.
+    stream: Box<dyn StreamDecoder>,
+    /// Whether malformed data was reported and no frame was decoded since.
+    malformed: bool,
+}
+
+impl fmt::Debug for DefmtDecoder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DefmtDecoder").finish_non_exhaustive()
     }
 }
 
-struct RttBuffer([u8; 1024]);
+/// How many bytes of a file are read at once while it is sent to a down channel.
+const FILE_CHUNK: usize = 4096;
+
+/// A file being streamed to a down channel.
+#[derive(Debug)]
+struct FileTransfer {
+    name: String,
+    file: fs::File,
+    size: u64,
+    /// How many bytes were written to the down channel so far.
+    sent: u64,
+    /// Bytes read from the file which did not fit into the down channel yet.
+    chunk: Vec<u8>,
+}
+
+impl FileTransfer {
+    /// Writes the file to `down_channel` until its buffer is full.
+    ///
+    /// Returns true once the whole file is written.
+    fn write(
+        &mut self,
+        down_channel: &mut dyn DownSink,
+        target: &mut Target,
+    ) -> anyhow::Result<bool> {
+        loop {
+            if self.chunk.is_empty() {
+                self.chunk.resize(FILE_CHUNK, 0);
+                let count = self.file.read(&mut self.chunk)?;
+                self.chunk.truncate(count);
+                if count == 0 {
+                    return Ok(true);
+                }
+            }
+
+            match down_channel.write(target, &self.chunk)? {
+                0 => return Ok(false),
+                count => {
+                    self.chunk.drain(..count);
+                    self.sent += count as u64;
+                }
+            }
+        }
+    }
+}
+
+/// Parses hex encoded bytes, e.g. `de ad be ef`, `0xde, 0xad` or `deadbeef`.
+fn parse_hex(input: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+
+    for token in input
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|token| !token.is_empty())
+    {
+        let digits = token
+            .strip_prefix("0x")
+            .or_else(|| token.strip_prefix("0X"))
+            .unwrap_or(token);
+
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("Invalid hex bytes '{}'", token));
+        }
+        if digits.len() % 2 != 0 {
+            return Err(format!("Odd number of hex digits in '{}'", token));
+        }
+
+        for i in (0..digits.len()).step_by(2) {
+            // Only ASCII hex digits are left, so this can't fail.
+            bytes.push(u8::from_str_radix(&digits[i..i + 2], 16).unwrap());
+        }
+    }
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+pub(super) mod test {
+    use std::{collections::BTreeMap, path::PathBuf};
+
+    use defmt_decoder::{Location, Locations, StringEntry, Table, TableEntry, Tag};
+
+    use super::{parse_hex, ChannelState, DataFormat, DefmtState};
+    use crate::rttui::{
+        scrollback::ScrollbackLimit,
+        source::MemoryChannel,
+        timestamp::{DefmtTimestamp, TimestampConfig},
+    };
+
+    #[test]
+    fn hex_input() {
+        assert_eq!(parse_hex("de ad be ef"), Ok(vec![0xde, 0xad, 0xbe, 0xef]));
+        assert_eq!(parse_hex("0xde, 0XAD"), Ok(vec![0xde, 0xad]));
+        assert_eq!(parse_hex("c0ffee"), Ok(vec![0xc0, 0xff, 0xee]));
+        assert_eq!(parse_hex(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn invalid_hex_input() {
+        assert!(parse_hex("abc").is_err());
+        assert!(parse_hex("0x").is_err());
+        assert!(parse_hex("+1").is_err());
+        assert!(parse_hex("zz").is_err());
+    }
+
+    #[test]
+    fn lines_split_across_reads() {
+        let target = MemoryChannel::new(Some("Terminal".to_owned()), 1024);
+        let mut channel = ChannelState::new(
+            Some(Box::new(target.clone())),
+            None,
+            None,
+            false,
+            DataFormat::String,
+            None,
+        );
+        assert_eq!(channel.name(), "Terminal");
+
+        target.push(b"first\nsec");
+        target.push(b"ond\nthi");
+        let received = channel.poll_rtt(None, 1);
+        assert_eq!(received.len(), 2);
+        assert_eq!(channel.new_lines().len(), 2);
+
+        target.push(b"rd\n");
+        channel.poll_rtt(None, 2);
+        let lines = channel
+            .scrollback()
+            .lines()
+            .map(|line| line.text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, ["first", "second", "third"]);
+    }
+
+    #[test]
+    fn marker_below_incomplete_line() {
+        let target = MemoryChannel::new(None, 1024);
+        let mut channel = ChannelState::new(
+            Some(Box::new(target.clone())),
+            None,
+            Some("Terminal".to_owned()),
+            false,
+            DataFormat::String,
+            None,
+        );
+
+        target.push(b"first\nsec");
+        channel.poll_rtt(None, 1);
+        channel.insert_marker(1, "marker".to_owned());
+        assert_eq!(channel.new_lines().len(), 1);
+
+        target.push(b"ond\nthird\n");
+        channel.poll_rtt(None, 2);
+        let lines = channel
+            .scrollback()
+            .lines()
+            .map(|line| (line.text.as_str(), line.marker))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                ("first", false),
+                ("second", false),
+                ("marker", true),
+                ("third", false)
+            ]
+        );
+        assert_eq!(channel.new_lines().len(), 3);
+    }
+
+    #[test]
+    fn binary_markers() {
+        let target = MemoryChannel::new(None, 1024);
+        let mut channel = ChannelState::new(
+            Some(Box::new(target.clone())),
+            None,
+            Some("Binary".to_owned()),
+            false,
+            DataFormat::BinaryLE,
+            None,
+        );
+        channel.set_scrollback_limit(ScrollbackLimit {
+            lines: 100,
+            bytes: 4,
+        });
+
+        target.push(&[1, 2]);
+        channel.poll_rtt(None, 1);
+        channel.insert_marker(1, "first".to_owned());
+        target.push(&[3, 4, 5]);
+        channel.poll_rtt(None, 2);
+        channel.insert_marker(2, "second".to_owned());
+
+        assert_eq!(
+            channel.data().iter().copied().collect::<Vec<_>>(),
+            [2, 3, 4, 5]
+        );
+        assert_eq!(
+            channel.data_markers().collect::<Vec<_>>(),
+            [(1, "first"), (4, "second")]
+        );
+    }
+
+    /// Returns a defmt table with the info messages `Hello` at index 1 and `World` at index 2,
+    /// logged in lines 12 and 13 of `src/main.rs`. Frames are encoded raw, as their index.
+    pub fn defmt_state() -> &'static DefmtState {
+        let entry = |string: &str| {
+            TableEntry::new(
+                StringEntry::new(Tag::Info, string.to_owned()),
+                format!("defmt_{}", string),
+            )
+        };
+        let entries = vec![(1, entry("Hello")), (2, entry("World"))]
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+        let location = |line| Location {
+            file: PathBuf::from("src/main.rs"),
+            line,
+            module: "app".to_owned(),
+        };
+        let locations = vec![(1, location(12)), (2, location(13))]
+            .into_iter()
+            .collect::<Locations>();
+        Box::leak(Box::new((Table::new(entries), Some(locations))))
+    }
+
+    #[test]
+    fn defmt_frames_split_across_reads() {
+        let target = MemoryChannel::new(None, 1024);
+        let mut channel = ChannelState::new(
+            Some(Box::new(target.clone())),
+            None,
+            Some("defmt".to_owned()),
+            false,
+            DataFormat::Defmt,
+            None,
+        );
+        channel.set_defmt(defmt_state());
+        channel.set_timestamps(
+            TimestampConfig {
+                defmt: DefmtTimestamp::Host,
+                ..TimestampConfig::default()
+            },
+            chrono::Local::now(),
+        );
+
+        // Two frames in one read, the second one completed by the next read.
+        target.push(&[0x01, 0x00, 0x02]);
+        channel.poll_rtt(None, 1);
+        assert_eq!(channel.scrollback().len(), 2);
+        target.push(&[0x00]);
+        target.push(&[0x01, 0x00]);
+        channel.poll_rtt(None, 2);
+
+        let lines = channel
+            .scrollback()
+            .lines()
+            .map(|line| line.text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "INFO  Hello",
+                "└─ src/main.rs:12",
+                "INFO  World",
+                "└─ src/main.rs:13",
+                "INFO  Hello",
+                "└─ src/main.rs:12"
+            ]
+        );
+    }
+
+    #[test]
+    fn defmt_after_malformed_frame() {
+        let target = MemoryChannel::new(None, 1024);
+        let mut channel = ChannelState::new(
+            Some(Box::new(target.clone())),
+            None,
+            Some("defmt".to_owned()),
+            false,
+            DataFormat::Defmt,
+            None,
+        );
+        channel.set_defmt(defmt_state());
+        channel.set_timestamps(
+            TimestampConfig {
+                defmt: DefmtTimestamp::Host,
+                ..TimestampConfig::default()
+            },
+            chrono::Local::now(),
+        );
+
+        // A frame with an unknown index, then a valid frame in the next read.
+        target.push(&[0x07, 0x00]);
+        channel.poll_rtt(None, 1);
+        assert_eq!(channel.scrollback().len(), 0);
+        target.push(&[0x02, 0x00]);
+        channel.poll_rtt(None, 2);
+
+        let lines = channel
+            .scrollback()
+            .lines()
+            .map(|line| line.text.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, ["INFO  World", "└─ src/main.rs:13"]);
+    }
+
+    #[test]
+    fn input_written_in_chunks() {
+        let target = MemoryChannel::new(None, 4);
+        let mut channel = ChannelState::new(
+            None,
+            Some(Box::new(target.clone())),
+            Some("Input".to_owned()),
+            false,
+            DataFormat::String,
+            None,
+        );
+        "0123456789"
+            .chars()
+            .for_each(|c| channel.input_mut().insert(c));
+        channel.push_rtt(None);
+        assert_eq!(target.written(), b"0123456789\n");
+    }
+
+    #[test]
+    fn input_held_back_while_sending_file() {
+        let path = std::env::temp_dir().join(format!("embed-send-{}", std::process::id()));
+        std::fs::write(&path, [b'f'; 10]).unwrap();
+
+        let target = MemoryChannel::new(None, 4);
+        target.set_free(Some(6));
+        let mut channel = ChannelState::new(
+            None,
+            Some(Box::new(target.clone())),
+            Some("Input".to_owned()),
+            false,
+            DataFormat::String,
+            None,
+        );
+        let type_line = |channel: &mut ChannelState, text: &str| {
+            text.chars().for_each(|c| channel.input_mut().insert(c));
+        };
+
+        type_line(&mut channel, path.to_str().unwrap());
+        channel.send_file(None);
+        assert_eq!(
+            channel.sending_progress().unwrap(),
+            format!("Sending {}: 6/10 bytes", path.display())
+        );
+
+        type_line(&mut channel, "typed");
+        channel.push_rtt(None);
+        assert_eq!(target.written(), b"ffffff");
 
-impl fmt::Debug for RttBuffer {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.0.fmt(f)
+        target.set_free(None);
+        channel.write_pending(None);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(channel.sending_progress(), None);
+        assert_eq!(target.written(), b"fffffffffftyped\n");
+        assert_eq!(
+            channel.notice(),
+            Some(format!("Sent {} (10 bytes)", path.display()).as_str())
+        );
     }
 }
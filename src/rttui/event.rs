@@ -3,12 +3,25 @@ use std::sync::{atomic::AtomicBool, Arc};
 use std::thread;
 use std::time::Duration;
 
-use crossterm::event::{self, Event as CEvent, KeyEvent};
+use crossterm::event::{self, Event as CEvent};
+
+/// Where the UI gets its input events from.
+pub trait EventSource {
+    /// Waits up to `timeout` for the next event.
+    fn next(&self, timeout: Duration) -> Result<CEvent, mpsc::RecvTimeoutError>;
+}
+
+/// Events sent through a channel, e.g. scripted ones to drive the UI in tests.
+impl EventSource for mpsc::Receiver<CEvent> {
+    fn next(&self, timeout: Duration) -> Result<CEvent, mpsc::RecvTimeoutError> {
+        self.recv_timeout(timeout)
+    }
+}
 
 /// A small event handler that wrap termion input and tick events. Each event
 /// type is handled in its own thread and returned to a common `Receiver`
 pub struct Events {
-    rx: mpsc::Receiver<KeyEvent>,
+    rx: mpsc::Receiver<CEvent>,
     _input_handle: thread::JoinHandle<()>,
     _ignore_exit_key: Arc<AtomicBool>,
 }
@@ -41,8 +54,10 @@ impl Events {
                     loop {
                         // poll for tick rate duration, if no events, sent tick event.
                         if event::poll(config.poll_rate).unwrap() {
-                            if let CEvent::Key(key) = event::read().unwrap() {
-                                if tx.send(key).is_err() {
+                            if let event @ (CEvent::Key(_) | CEvent::Mouse(_)) =
+                                event::read().unwrap()
+                            {
+                                if tx.send(event).is_err() {
                                     return;
                                 }
                             }
@@ -58,8 +73,10 @@ impl Events {
             _input_handle: input_handle,
         }
     }
+}
 
-    pub fn next(&self, timeout: Duration) -> Result<KeyEvent, mpsc::RecvTimeoutError> {
+impl EventSource for Events {
+    fn next(&self, timeout: Duration) -> Result<CEvent, mpsc::RecvTimeoutError> {
         self.rx.recv_timeout(timeout)
     }
 }
@@ -0,0 +1,313 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+/// The maximum number of commands kept in the history of a channel.
+const HISTORY_SIZE: usize = 500;
+
+/// The input line of a down channel, with cursor movement, history and completion.
+#[derive(Debug, Default)]
+pub struct LineEditor {
+    line: String,
+    /// Byte offset of the cursor into `line`, always on a character boundary.
+    cursor: usize,
+    /// Previously sent commands, oldest first.
+    history: Vec<String>,
+    /// The history entry currently shown, while browsing the history.
+    history_index: Option<usize>,
+    /// The line being edited before browsing the history started.
+    draft: String,
+    /// Where the history is kept between sessions.
+    history_file: Option<PathBuf>,
+    /// Commands offered for completion.
+    completions: Vec<String>,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the history from `path` and keeps saving it there on every new command.
+    pub fn set_history_file(&mut self, path: PathBuf) {
+        match fs::read_to_string(&path) {
+            Ok(content) => self.history = content.lines().map(Into::into).collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => log::warn!("Could not read input history {}: {}", path.display(), e),
+        }
+        self.history_file = Some(path);
+    }
+
+    pub fn set_completions(&mut self, completions: Vec<String>) {
+        self.completions = completions;
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.line
+    }
+
+    /// The cursor position in characters.
+    pub fn cursor(&self) -> usize {
+        self.line[..self.cursor].chars().count()
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.line.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    /// Deletes the character before the cursor.
+    pub fn backspace(&mut self) {
+        if let Some(start) = self.previous_boundary() {
+            self.line.drain(start..self.cursor);
+            self.cursor = start;
+        }
+    }
+
+    /// Deletes the character under the cursor.
+    pub fn delete(&mut self) {
+        if let Some(end) = self.next_boundary() {
+            self.line.drain(self.cursor..end);
+        }
+    }
+
+    pub fn left(&mut self) {
+        if let Some(start) = self.previous_boundary() {
+            self.cursor = start;
+        }
+    }
+
+    pub fn right(&mut self) {
+        if let Some(end) = self.next_boundary() {
+            self.cursor = end;
+        }
+    }
+
+    pub fn home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor = self.line.len();
+    }
+
+    /// Deletes the word before the cursor, including trailing whitespace.
+    pub fn delete_word(&mut self) {
+        let before = &self.line[..self.cursor];
+        let start = before
+            .trim_end()
+            .rfind(char::is_whitespace)
+            .map_or(0, |i| i + 1);
+        self.line.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    /// Deletes everything before the cursor.
+    pub fn delete_to_start(&mut self) {
+        self.line.drain(..self.cursor);
+        self.cursor = 0;
+    }
+
+    /// Shows the previous command of the history.
+    pub fn history_previous(&mut self) {
+        let index = match self.history_index {
+            Some(0) => return,
+            Some(i) => i - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = self.line.clone();
+                self.history.len() - 1
+            }
+        };
+        self.history_index = Some(index);
+        self.set_line(self.history[index].clone());
+    }
+
+    /// Shows the next command of the history, or the line edited before browsing the history.
+    pub fn history_next(&mut self) {
+        match self.history_index {
+            Some(i) if i + 1 < self.history.len() => {
+                self.history_index = Some(i + 1);
+                self.set_line(self.history[i + 1].clone());
+            }
+            Some(_) => {
+                self.history_index = None;
+                let draft = std::mem::take(&mut self.draft);
+                self.set_line(draft);
+            }
+            None => {}
+        }
+    }
+
+    /// Completes the line to the longest common prefix of all matching commands.
+    pub fn complete(&mut self) {
+        let mut matches = self
+            .completions
+            .iter()
+            .filter(|c| c.starts_with(self.line.as_str()));
+
+        let mut prefix = match matches.next() {
+            Some(first) => first.as_str(),
+            None => return,
+        };
+        for other in matches {
+            let common = prefix
+                .char_indices()
+                .zip(other.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(prefix.len().min(other.len()), |((i, _), _)| i);
+            prefix = &prefix[..common];
+        }
+
+        let prefix = prefix.to_owned();
+        self.set_line(prefix);
+    }
+
+    /// Takes the line for sending and records it in the history.
+    pub fn submit(&mut self) -> String {
+        let line = std::mem::take(&mut self.line);
+        self.cursor = 0;
+        self.history_index = None;
+
+        if !line.is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line.clone());
+            if self.history.len() > HISTORY_SIZE {
+                self.history.remove(0);
+            }
+            if let Err(e) = self.save_history() {
+                log::warn!("Could not save input history: {}", e);
+            }
+        }
+
+        line
+    }
+
+    fn save_history(&self) -> io::Result<()> {
+        if let Some(path) = &self.history_file {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut file = fs::File::create(path)?;
+            for line in &self.history {
+                writeln!(file, "{}", line)?;
+            }
+            file.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the part of the line visible in `width` columns and the cursor column within it.
+    pub fn view(&self, width: usize) -> (String, usize) {
+        let cursor = self.cursor();
+        let skip = (cursor + 1).saturating_sub(width);
+        let visible = self.line.chars().skip(skip).take(width).collect();
+        (visible, cursor - skip)
+    }
+
+    fn set_line(&mut self, line: String) {
+        self.line = line;
+        self.cursor = self.line.len();
+    }
+
+    fn previous_boundary(&self) -> Option<usize> {
+        self.line[..self.cursor]
+            .char_indices()
+            .last()
+            .map(|(i, _)| i)
+    }
+
+    fn next_boundary(&self) -> Option<usize> {
+        self.line[self.cursor..]
+            .chars()
+            .next()
+            .map(|c| self.cursor + c.len_utf8())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LineEditor;
+
+    fn editor(line: &str) -> LineEditor {
+        let mut editor = LineEditor::new();
+        line.chars().for_each(|c| editor.insert(c));
+        editor
+    }
+
+    #[test]
+    fn cursor_editing() {
+        let mut editor = editor("hllo");
+        editor.home();
+        editor.right();
+        editor.insert('e');
+        assert_eq!(editor.as_str(), "hello");
+        assert_eq!(editor.cursor(), 2);
+
+        editor.end();
+        editor.left();
+        editor.delete();
+        editor.backspace();
+        assert_eq!(editor.as_str(), "hel");
+    }
+
+    #[test]
+    fn word_and_line_deletion() {
+        let mut editor = editor("set led  on ");
+        editor.delete_word();
+        assert_eq!(editor.as_str(), "set led  ");
+        editor.delete_word();
+        assert_eq!(editor.as_str(), "set ");
+
+        editor.insert('x');
+        editor.left();
+        editor.delete_to_start();
+        assert_eq!(editor.as_str(), "x");
+        assert_eq!(editor.cursor(), 0);
+    }
+
+    #[test]
+    fn multibyte_characters() {
+        let mut editor = editor("äöü");
+        editor.left();
+        editor.backspace();
+        assert_eq!(editor.as_str(), "äü");
+        assert_eq!(editor.cursor(), 1);
+    }
+
+    #[test]
+    fn history() {
+        let mut editor = editor("first");
+        editor.submit();
+        "second".chars().for_each(|c| editor.insert(c));
+        editor.submit();
+        "draft".chars().for_each(|c| editor.insert(c));
+
+        editor.history_previous();
+        assert_eq!(editor.as_str(), "second");
+        editor.history_previous();
+        editor.history_previous();
+        assert_eq!(editor.as_str(), "first");
+        editor.history_next();
+        editor.history_next();
+        assert_eq!(editor.as_str(), "draft");
+    }
+
+    #[test]
+    fn completion() {
+        let mut editor = editor("st");
+        editor.set_completions(vec!["status".into(), "start".into(), "reset".into()]);
+        editor.complete();
+        assert_eq!(editor.as_str(), "sta");
+        editor.insert('r');
+        editor.complete();
+        assert_eq!(editor.as_str(), "start");
+    }
+
+    #[test]
+    fn view_follows_cursor() {
+        let editor = editor("0123456789");
+        assert_eq!(editor.view(5), ("6789".to_owned(), 4));
+    }
+}
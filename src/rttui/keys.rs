@@ -0,0 +1,426 @@
+use std::{collections::BTreeMap, convert::TryFrom, fmt, str::FromStr};
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// An action of the RTT UI which can be bound to a key.
+#[derive(
+    Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    Clear,
+    Split,
+    NextPane,
+    NextTab,
+    PreviousTab,
+    TogglePlot,
+    ToggleHex,
+    SendFile,
+    ScrollUp,
+    ScrollDown,
+    ScrollLineUp,
+    ScrollLineDown,
+    ScrollToStart,
+    ScrollToEnd,
+    Marker,
+    PreviousMarker,
+    NextMarker,
+    Pause,
+    Help,
+}
+
+impl Action {
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "Save the logs and quit",
+            Action::Clear => "Clear the current tab",
+            Action::Split => "Switch the split layout",
+            Action::NextPane => "Focus the next pane",
+            Action::NextTab => "Select the next tab",
+            Action::PreviousTab => "Select the previous tab",
+            Action::TogglePlot => "Switch between log and plot view",
+            Action::ToggleHex => "Switch the input line to hex",
+            Action::SendFile => "Send the file named in the input line",
+            Action::ScrollUp => "Scroll up one screen",
+            Action::ScrollDown => "Scroll down one screen",
+            Action::ScrollLineUp => "Scroll up one line",
+            Action::ScrollLineDown => "Scroll down one line",
+            Action::ScrollToStart => "Scroll to the oldest line",
+            Action::ScrollToEnd => "Scroll to the newest line and follow new lines",
+            Action::Marker => "Insert a marker into all tabs",
+            Action::PreviousMarker => "Jump to the previous marker",
+            Action::NextMarker => "Jump to the next marker",
+            Action::Pause => "Freeze or resume the view of the current tab",
+            Action::Help => "Show this help",
+        }
+    }
+}
+
+/// A key together with its modifiers, written like `Ctrl+C`, `Alt+Q`, `F5` or `Shift+Tab`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    /// Creates a chord, normalized the way terminals report keys inconsistently:
+    /// Shift is part of the character itself and of `BackTab`, and letters combined with
+    /// Ctrl or Alt are lowercase.
+    fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let modifiers =
+            modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        match code {
+            KeyCode::Char(c) if modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+                Self {
+                    code: KeyCode::Char(c.to_ascii_lowercase()),
+                    modifiers: modifiers - KeyModifiers::SHIFT,
+                }
+            }
+            KeyCode::Char(_) | KeyCode::BackTab => Self {
+                code,
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            KeyCode::Tab if modifiers.contains(KeyModifiers::SHIFT) => Self {
+                code: KeyCode::BackTab,
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            _ => Self { code, modifiers },
+        }
+    }
+}
+
+impl From<KeyEvent> for KeyChord {
+    fn from(event: KeyEvent) -> Self {
+        Self::new(event.code, event.modifiers)
+    }
+}
+
+impl FromStr for KeyChord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // The key itself may be a `+`.
+        let (modifier_names, key) = if s == "+" {
+            ("", "+")
+        } else if let Some(modifier_names) = s.strip_suffix("++") {
+            (modifier_names, "+")
+        } else {
+            s.rsplit_once('+').unwrap_or(("", s))
+        };
+
+        let mut modifiers = KeyModifiers::NONE;
+        for name in modifier_names.split('+').filter(|name| !name.is_empty()) {
+            modifiers |= match name.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("Unknown modifier '{}' in key '{}'", name, s)),
+            };
+        }
+
+        let code = match key.to_ascii_lowercase().as_str() {
+            "enter" => KeyCode::Enter,
+            "esc" | "escape" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "space" => KeyCode::Char(' '),
+            name => {
+                let mut chars = key.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => match name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                        Some(n) if (1..=12).contains(&n) => KeyCode::F(n),
+                        _ => return Err(format!("Unknown key '{}'", s)),
+                    },
+                }
+            }
+        };
+
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl TryFrom<String> for KeyChord {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<KeyChord> for String {
+    fn from(chord: KeyChord) -> Self {
+        chord.to_string()
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "Shift+")?;
+        }
+
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) if !self.modifiers.is_empty() => {
+                write!(f, "{}", c.to_ascii_uppercase())
+            }
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            KeyCode::BackTab => write!(f, "Shift+Tab"),
+            KeyCode::Esc => write!(f, "Esc"),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+/// Returns the keys the UI handles itself, which can't be bound to an action: selecting tabs
+/// and editing the input line.
+fn fixed_keys() -> Vec<KeyChord> {
+    let mut keys = (1..=12)
+        .map(|n| KeyChord::new(KeyCode::F(n), KeyModifiers::NONE))
+        .collect::<Vec<_>>();
+    keys.extend(('0'..='9').map(|c| KeyChord::new(KeyCode::Char(c), KeyModifiers::ALT)));
+    keys.extend(
+        ['a', 'e', 'w', 'u']
+            .iter()
+            .map(|&c| KeyChord::new(KeyCode::Char(c), KeyModifiers::CONTROL)),
+    );
+    keys.extend(
+        [
+            KeyCode::Enter,
+            KeyCode::Backspace,
+            KeyCode::Delete,
+            KeyCode::Left,
+            KeyCode::Right,
+            KeyCode::Up,
+            KeyCode::Down,
+            KeyCode::Home,
+            KeyCode::End,
+        ]
+        .iter()
+        .map(|&code| KeyChord::new(code, KeyModifiers::NONE)),
+    );
+    keys
+}
+
+/// Maps actions to the keys which trigger them.
+///
+/// A key can only be bound to one action, and not to the keys of [fixed_keys]. An action bound
+/// to `""` has no key.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(
+    try_from = "BTreeMap<Action, String>",
+    into = "BTreeMap<Action, String>"
+)]
+pub struct KeyBindings(BTreeMap<Action, KeyChord>);
+
+impl TryFrom<BTreeMap<Action, String>> for KeyBindings {
+    type Error = String;
+
+    fn try_from(keys: BTreeMap<Action, String>) -> Result<Self, Self::Error> {
+        let mut bindings = BTreeMap::new();
+        for (action, key) in keys {
+            if !key.is_empty() {
+                bindings.insert(action, key.parse::<KeyChord>()?);
+            }
+        }
+
+        let fixed = fixed_keys();
+        for (action, chord) in &bindings {
+            if let Some((other, _)) = bindings
+                .range(..action)
+                .find(|(_, other_chord)| *other_chord == chord)
+            {
+                return Err(format!(
+                    "The key {} is bound to both {:?} and {:?}",
+                    chord, other, action
+                ));
+            }
+            if fixed.contains(chord) {
+                return Err(format!(
+                    "The key {} is bound to {:?}, but is used to select tabs or edit the input line",
+                    chord, action
+                ));
+            }
+        }
+        Ok(Self(bindings))
+    }
+}
+
+impl From<KeyBindings> for BTreeMap<Action, String> {
+    fn from(bindings: KeyBindings) -> Self {
+        bindings
+            .0
+            .into_iter()
+            .map(|(action, chord)| (action, chord.to_string()))
+            .collect()
+    }
+}
+
+impl KeyBindings {
+    /// Returns the action bound to the key of `event`, if any.
+    pub fn action(&self, event: KeyEvent) -> Option<Action> {
+        let chord = KeyChord::from(event);
+        self.0
+            .iter()
+            .find(|(_, bound)| **bound == chord)
+            .map(|(action, _)| *action)
+    }
+
+    /// Returns the keys and descriptions of all bindings, including the fixed ones.
+    pub fn help(&self) -> Vec<(String, &'static str)> {
+        let mut help = self
+            .0
+            .iter()
+            .map(|(action, chord)| (chord.to_string(), action.description()))
+            .collect::<Vec<_>>();
+        help.extend(
+            [
+                ("F1-F12, Alt+1-9", "Select a tab"),
+                ("Enter", "Send the input line"),
+                ("Up, Down", "Browse the input history"),
+                ("Up, Down, Home, End", "Scroll, in tabs without input line"),
+                ("Tab", "Complete the input line"),
+                (
+                    "Ctrl+A, Ctrl+E",
+                    "Move to the start or end of the input line",
+                ),
+                (
+                    "Ctrl+W, Ctrl+U",
+                    "Delete the previous word or everything before the cursor",
+                ),
+            ]
+            .iter()
+            .map(|&(keys, description)| (keys.to_owned(), description)),
+        );
+        help
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Action, KeyBindings, KeyChord};
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use figment::{
+        providers::{Format, Toml},
+        Figment,
+    };
+
+    #[test]
+    fn parse_and_display() {
+        for chord in [
+            "Ctrl+C",
+            "Alt+Q",
+            "F5",
+            "Shift+Tab",
+            "?",
+            "Ctrl++",
+            "PageUp",
+            "Space",
+        ] {
+            assert_eq!(chord.parse::<KeyChord>().unwrap().to_string(), chord);
+        }
+        assert_eq!("ctrl+c".parse::<KeyChord>().unwrap().to_string(), "Ctrl+C");
+        assert!("Hyper+C".parse::<KeyChord>().is_err());
+        assert!("F13".parse::<KeyChord>().is_err());
+    }
+
+    #[test]
+    fn reject_duplicate_keys() {
+        let parse = |toml| {
+            Figment::from(Toml::string(toml))
+                .extract_inner::<KeyBindings>("keys")
+                .map_err(|e| e.to_string())
+        };
+        assert!(parse("keys = { quit = \"Ctrl+C\", clear = \"Ctrl+L\" }").is_ok());
+        let error = parse("keys = { quit = \"Ctrl+C\", clear = \"ctrl+c\" }").unwrap_err();
+        assert!(
+            error.contains("The key Ctrl+C is bound to both Quit and Clear"),
+            "{}",
+            error
+        );
+        let error = parse("keys = { quit = \"Ctrl+A\" }").unwrap_err();
+        assert!(
+            error.contains("The key Ctrl+A is bound to Quit, but is used"),
+            "{}",
+            error
+        );
+        assert!(parse("keys = { quit = \"F3\" }").is_err());
+        assert!(parse("keys = { quit = \"Alt+1\" }").is_err());
+    }
+
+    #[test]
+    fn unbind_keys() {
+        let bindings = Figment::from(Toml::string(
+            "keys = { quit = \"Ctrl+C\", clear = \"Ctrl+L\" }",
+        ))
+        .merge(Toml::string("keys = { clear = \"\" }"))
+        .extract_inner::<KeyBindings>("keys")
+        .unwrap();
+        assert_eq!(
+            bindings.action(KeyEvent::new(KeyCode::Char('l'), KeyModifiers::CONTROL)),
+            None
+        );
+        assert_eq!(
+            bindings.action(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+            Some(Action::Quit)
+        );
+        assert!(bindings.help().iter().all(|(key, _)| key != "Ctrl+L"));
+    }
+
+    #[test]
+    fn match_events() {
+        let mut bindings = KeyBindings::default();
+        bindings.0.insert(Action::Quit, "Ctrl+C".parse().unwrap());
+        bindings
+            .0
+            .insert(Action::PreviousTab, "Shift+Tab".parse().unwrap());
+        bindings.0.insert(Action::Help, "?".parse().unwrap());
+
+        let event = |code, modifiers| KeyEvent::new(code, modifiers);
+        assert_eq!(
+            bindings.action(event(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+            Some(Action::Quit)
+        );
+        assert_eq!(
+            bindings.action(event(
+                KeyCode::Char('C'),
+                KeyModifiers::CONTROL | KeyModifiers::SHIFT
+            )),
+            Some(Action::Quit)
+        );
+        assert_eq!(
+            bindings.action(event(KeyCode::BackTab, KeyModifiers::SHIFT)),
+            Some(Action::PreviousTab)
+        );
+        assert_eq!(
+            bindings.action(event(KeyCode::Char('?'), KeyModifiers::SHIFT)),
+            Some(Action::Help)
+        );
+        assert_eq!(
+            bindings.action(event(KeyCode::Char('c'), KeyModifiers::NONE)),
+            None
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use tui::layout::{Constraint, Direction, Layout, Rect};
+
+/// How several channels are shown at the same time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum SplitLayout {
+    /// Only the selected channel is shown.
+    None,
+    /// The channels are shown side by side.
+    Horizontal,
+    /// The channels are stacked on top of each other.
+    Vertical,
+}
+
+impl SplitLayout {
+    /// Returns the layout that follows this one when cycling through the layouts.
+    pub fn next(self) -> Self {
+        match self {
+            SplitLayout::None => SplitLayout::Horizontal,
+            SplitLayout::Horizontal => SplitLayout::Vertical,
+            SplitLayout::Vertical => SplitLayout::None,
+        }
+    }
+
+    /// Divides `area` into `panes` equally sized areas.
+    pub fn areas(self, area: Rect, panes: usize) -> Vec<Rect> {
+        let direction = match self {
+            SplitLayout::None => return vec![area],
+            SplitLayout::Horizontal => Direction::Horizontal,
+            SplitLayout::Vertical => Direction::Vertical,
+        };
+
+        let panes = panes.max(1) as u32;
+        let constraints = (0..panes)
+            .map(|_| Constraint::Ratio(1, panes))
+            .collect::<Vec<_>>();
+
+        Layout::default()
+            .direction(direction)
+            .constraints(constraints)
+            .split(area)
+    }
+}
+
+/// Picks the tabs shown when splitting the screen.
+///
+/// Invalid indices in `configured` are ignored. If no valid tab remains, the first two tabs
+/// are shown. At least one pane is always returned.
+pub fn initial_panes(configured: &[usize], tab_count: usize) -> Vec<usize> {
+    let mut panes = Vec::new();
+    for &tab in configured {
+        if tab < tab_count && !panes.contains(&tab) {
+            panes.push(tab);
+        }
+    }
+
+    if panes.is_empty() {
+        panes.extend(0..tab_count.clamp(1, 2));
+    }
+
+    panes
+}
+
+#[cfg(test)]
+mod test {
+    use tui::layout::Rect;
+
+    use super::{initial_panes, SplitLayout};
+
+    #[test]
+    fn panes() {
+        assert_eq!(initial_panes(&[2, 7, 2, 0], 3), vec![2, 0]);
+        assert_eq!(initial_panes(&[7], 3), vec![0, 1]);
+        assert_eq!(initial_panes(&[], 1), vec![0]);
+        assert_eq!(initial_panes(&[], 0), vec![0]);
+    }
+
+    #[test]
+    fn areas() {
+        let area = Rect::new(0, 0, 40, 10);
+        assert_eq!(SplitLayout::None.areas(area, 3), vec![area]);
+        assert_eq!(
+            SplitLayout::Horizontal.areas(area, 2),
+            vec![Rect::new(0, 0, 20, 10), Rect::new(20, 0, 20, 10)]
+        );
+        assert_eq!(
+            SplitLayout::Vertical.areas(area, 2),
+            vec![Rect::new(0, 0, 40, 5), Rect::new(0, 5, 40, 5)]
+        );
+        assert_eq!(SplitLayout::Vertical.areas(area, 0), vec![area]);
+        assert_eq!(SplitLayout::Vertical.next(), SplitLayout::None);
+    }
+}
@@ -1,3 +1,14 @@
 pub mod app;
 pub mod channel;
 pub mod event;
+pub mod input;
+pub mod keys;
+pub mod layout;
+pub mod mouse;
+pub mod plot;
+pub mod recording;
+pub mod scrollback;
+pub mod source;
+pub mod status;
+pub mod theme;
+pub mod timestamp;
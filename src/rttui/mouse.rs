@@ -0,0 +1,183 @@
+use std::io::{self, Write};
+
+use tui::{
+    buffer::Buffer,
+    layout::Rect,
+    style::{Modifier, Style},
+    widgets::Widget,
+};
+
+/// A text selection made by dragging the mouse, in screen coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+    /// Where the drag started, as `(column, row)`.
+    pub anchor: (u16, u16),
+    /// Where the mouse currently is, as `(column, row)`.
+    pub cursor: (u16, u16),
+}
+
+impl Selection {
+    pub fn new(column: u16, row: u16) -> Self {
+        Self {
+            anchor: (column, row),
+            cursor: (column, row),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchor == self.cursor
+    }
+
+    /// Returns the start and end of the selection in reading order.
+    fn ordered(&self) -> ((u16, u16), (u16, u16)) {
+        let (a, c) = (self.anchor, self.cursor);
+        if (a.1, a.0) <= (c.1, c.0) {
+            (a, c)
+        } else {
+            (c, a)
+        }
+    }
+
+    /// Returns one area per selected row, clipped to `bounds`.
+    pub fn rows(&self, bounds: Rect) -> Vec<Rect> {
+        let (start, end) = self.ordered();
+        (start.1..=end.1)
+            .filter(|&y| y >= bounds.top() && y < bounds.bottom())
+            .map(|y| {
+                let first = if y == start.1 { start.0 } else { bounds.left() };
+                let last = if y == end.1 {
+                    end.0
+                } else {
+                    bounds.right().saturating_sub(1)
+                };
+                let first = first.max(bounds.left());
+                let last = last.min(bounds.right().saturating_sub(1));
+                Rect {
+                    x: first,
+                    y,
+                    width: (last + 1).saturating_sub(first),
+                    height: 1,
+                }
+            })
+            .collect()
+    }
+
+    /// Extracts the selected text from a rendered frame.
+    pub fn text(&self, buffer: &Buffer) -> String {
+        self.rows(buffer.area)
+            .iter()
+            .map(|row| {
+                let line = (row.left()..row.right())
+                    .map(|x| buffer.get(x, row.y).symbol.as_str())
+                    .collect::<String>();
+                line.trim_end().to_owned()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Highlights the area it is rendered to, used to show the selection.
+pub struct Highlight;
+
+impl Widget for Highlight {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        buf.set_style(area, Style::default().add_modifier(Modifier::REVERSED));
+    }
+}
+
+/// Returns the index of the tab whose title is at `column` of the tab bar.
+///
+/// This mirrors how [tui::widgets::Tabs] lays out its titles: each title is padded by one
+/// column on either side and followed by a one column wide divider.
+pub fn tab_at<'a>(
+    names: impl IntoIterator<Item = &'a str>,
+    left: u16,
+    column: u16,
+) -> Option<usize> {
+    let mut x = left;
+    for (i, name) in names.into_iter().enumerate() {
+        let start = x + 1;
+        let end = start + name.chars().count() as u16;
+        if column >= x && column <= end {
+            return Some(i);
+        }
+        x = end + 2;
+    }
+    None
+}
+
+/// Copies `text` to the clipboard of the terminal using the OSC 52 escape sequence.
+///
+/// This works over SSH as well, as long as the terminal supports it.
+pub fn copy_to_clipboard(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    write!(stdout, "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+    stdout.flush()
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod test {
+    use super::{base64, tab_at, Selection};
+    use tui::{buffer::Buffer, layout::Rect};
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn tab_positions() {
+        // Rendered as " one | two | three "
+        let names = ["one", "two", "three"];
+        assert_eq!(tab_at(names, 0, 1), Some(0));
+        assert_eq!(tab_at(names, 0, 3), Some(0));
+        assert_eq!(tab_at(names, 0, 7), Some(1));
+        assert_eq!(tab_at(names, 0, 13), Some(2));
+        assert_eq!(tab_at(names, 0, 30), None);
+    }
+
+    #[test]
+    fn selected_text() {
+        let buffer = Buffer::with_lines(vec!["first line ", "second line", "third      "]);
+        let mut selection = Selection::new(6, 2);
+        selection.cursor = (6, 0);
+
+        assert_eq!(selection.text(&buffer), "line\nsecond line\nthird");
+        assert_eq!(
+            selection.rows(Rect::new(0, 0, 11, 3)),
+            vec![
+                Rect::new(6, 0, 5, 1),
+                Rect::new(0, 1, 11, 1),
+                Rect::new(0, 2, 7, 1)
+            ]
+        );
+    }
+}
@@ -0,0 +1,469 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    path::Path,
+    time::Instant,
+};
+
+use chrono::{DateTime, Local};
+
+use super::channel::DataFormat;
+
+/// The selectable time windows of the plot view, in seconds.
+const WINDOWS: [f64; 7] = [1.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+/// The longest incomplete line kept while waiting for its end, in bytes.
+const MAX_PENDING: usize = 4096;
+
+/// How numeric samples are extracted from the data of a channel.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum SampleFormat {
+    /// Text lines containing `key=value` pairs. Every key becomes its own series.
+    KeyValue,
+    U8,
+    I8,
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+}
+
+impl SampleFormat {
+    /// The width of a single binary sample in bytes, `None` for text samples.
+    fn width(self) -> Option<usize> {
+        match self {
+            SampleFormat::KeyValue => None,
+            SampleFormat::U8 | SampleFormat::I8 => Some(1),
+            SampleFormat::U16 | SampleFormat::I16 => Some(2),
+            SampleFormat::U32 | SampleFormat::I32 | SampleFormat::F32 => Some(4),
+        }
+    }
+
+    /// Returns true if samples of this format can be taken from channels with `format`.
+    ///
+    /// Text samples are only read from string channels, binary samples only from binary ones.
+    pub fn accepts(self, format: DataFormat) -> bool {
+        match self {
+            SampleFormat::KeyValue => format == DataFormat::String,
+            _ => format == DataFormat::BinaryLE,
+        }
+    }
+
+    /// Decodes a little endian sample of [SampleFormat::width] bytes.
+    fn decode(self, bytes: &[u8]) -> f64 {
+        match self {
+            SampleFormat::KeyValue => unreachable!("text samples are not decoded from bytes"),
+            SampleFormat::U8 => bytes[0] as f64,
+            SampleFormat::I8 => bytes[0] as i8 as f64,
+            SampleFormat::U16 => u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            SampleFormat::I16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            SampleFormat::U32 => {
+                u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64
+            }
+            SampleFormat::I32 => {
+                i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64
+            }
+            SampleFormat::F32 => {
+                f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64
+            }
+        }
+    }
+}
+
+/// The plot config of a single channel.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct PlotConfig {
+    /// How samples are encoded in the channel data.
+    pub samples: SampleFormat,
+    /// Names of the plotted series.
+    ///
+    /// For binary samples, every record contains one sample per series, in this order.
+    /// For `KeyValue` samples, only the listed keys are plotted, or all keys if empty.
+    #[serde(default)]
+    pub series: Vec<String>,
+    /// The initially visible time window in seconds.
+    #[serde(default = "default_window")]
+    pub window: f64,
+}
+
+fn default_window() -> f64 {
+    10.0
+}
+
+/// A named series of `(time, value)` points.
+#[derive(Debug, Clone)]
+pub struct Series {
+    pub name: String,
+    pub points: Vec<(f64, f64)>,
+}
+
+/// Holds the numeric samples of a channel and the state of its plot view.
+#[derive(Debug)]
+pub struct PlotState {
+    config: PlotConfig,
+    start: Instant,
+    /// When the data at time 0 of the plot was received, set by the first samples.
+    origin: Option<DateTime<Local>>,
+    /// How far the time of the data is ahead of the time since `start`, e.g. while replaying a
+    /// recording.
+    offset: f64,
+    series: Vec<Series>,
+    /// Bytes of an incomplete line or record, kept until the rest arrives.
+    pending: Vec<u8>,
+    window: f64,
+    /// The end of the visible window while the plot is paused.
+    paused_at: Option<f64>,
+    /// The series as they were when the plot was paused, shown until it is resumed.
+    snapshot: Vec<Series>,
+    /// A message shown in the plot status line, e.g. after an export.
+    status: Option<String>,
+}
+
+impl PlotState {
+    pub fn new(config: PlotConfig) -> Self {
+        let mut series = Vec::new();
+        if config.samples != SampleFormat::KeyValue {
+            if config.series.is_empty() {
+                series.push(Series {
+                    name: "value".to_owned(),
+                    points: Vec::new(),
+                });
+            } else {
+                series.extend(config.series.iter().map(|name| Series {
+                    name: name.clone(),
+                    points: Vec::new(),
+                }));
+            }
+        }
+
+        Self {
+            window: config.window,
+            config,
+            start: Instant::now(),
+            origin: None,
+            offset: 0.0,
+            series,
+            pending: Vec::new(),
+            paused_at: None,
+            snapshot: Vec::new(),
+            status: None,
+        }
+    }
+
+    pub fn config(&self) -> &PlotConfig {
+        &self.config
+    }
+
+    /// The current time of the plot in seconds, following the time the data was received at.
+    pub fn now(&self) -> f64 {
+        self.start.elapsed().as_secs_f64() + self.offset
+    }
+
+    /// Extracts all complete samples from `bytes` and stamps them with the time they were
+    /// `received`, which is the recorded time while replaying.
+    pub fn ingest(&mut self, bytes: &[u8], received: DateTime<Local>) {
+        let elapsed = self.start.elapsed();
+        let origin = *self.origin.get_or_insert_with(|| {
+            received
+                - chrono::Duration::from_std(elapsed).unwrap_or_else(|_| chrono::Duration::zero())
+        });
+        let time = (received - origin)
+            .to_std()
+            .map_or(0.0, |time| time.as_secs_f64());
+        self.offset = time - elapsed.as_secs_f64();
+        self.ingest_at(bytes, time);
+    }
+
+    /// Extracts all complete samples from `bytes` and stamps them with `time`.
+    pub fn ingest_at(&mut self, bytes: &[u8], time: f64) {
+        self.pending.extend_from_slice(bytes);
+
+        match self.config.samples.width() {
+            None => {
+                while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+                    let line = self.pending.drain(..=end).collect::<Vec<_>>();
+                    let line = String::from_utf8_lossy(&line);
+                    for (key, value) in parse_key_values(&line) {
+                        if self.config.series.is_empty()
+                            || self.config.series.iter().any(|s| s == key)
+                        {
+                            self.push(key, time, value);
+                        }
+                    }
+                }
+                // A line without end is most likely no sample at all, so it is not kept forever.
+                if self.pending.len() > MAX_PENDING {
+                    self.pending.clear();
+                }
+            }
+            Some(width) => {
+                let record_len = width * self.series.len();
+                while self.pending.len() >= record_len {
+                    let record = self.pending.drain(..record_len).collect::<Vec<_>>();
+                    for (series, sample) in self.series.iter_mut().zip(record.chunks(width)) {
+                        series
+                            .points
+                            .push((time, self.config.samples.decode(sample)));
+                    }
+                }
+            }
+        }
+
+        // Only keep as much history as the largest window can show, a paused plot shows its
+        // snapshot instead.
+        let oldest = time - WINDOWS[WINDOWS.len() - 1];
+        for series in &mut self.series {
+            let expired = series.points.partition_point(|&(t, _)| t < oldest);
+            series.points.drain(..expired);
+        }
+    }
+
+    fn push(&mut self, name: &str, time: f64, value: f64) {
+        match self.series.iter_mut().find(|s| s.name == name) {
+            Some(series) => series.points.push((time, value)),
+            None => self.series.push(Series {
+                name: name.to_owned(),
+                points: vec![(time, value)],
+            }),
+        }
+    }
+
+    /// Returns the bounds of the visible time window.
+    pub fn bounds(&self) -> [f64; 2] {
+        let end = self.paused_at.unwrap_or_else(|| self.now());
+        [end - self.window, end]
+    }
+
+    /// Returns the points of all series within `bounds`.
+    pub fn visible(&self, bounds: [f64; 2]) -> Vec<Series> {
+        let series = if self.paused_at.is_some() {
+            &self.snapshot
+        } else {
+            &self.series
+        };
+        series
+            .iter()
+            .map(|series| Series {
+                name: series.name.clone(),
+                points: series
+                    .points
+                    .iter()
+                    .filter(|(t, _)| *t >= bounds[0] && *t <= bounds[1])
+                    .copied()
+                    .collect(),
+            })
+            .collect()
+    }
+
+    pub fn window(&self) -> f64 {
+        self.window
+    }
+
+    /// Selects the next larger time window.
+    pub fn zoom_out(&mut self) {
+        if let Some(&w) = WINDOWS.iter().find(|&&w| w > self.window) {
+            self.window = w;
+        }
+    }
+
+    /// Selects the next smaller time window.
+    pub fn zoom_in(&mut self) {
+        if let Some(&w) = WINDOWS.iter().rev().find(|&&w| w < self.window) {
+            self.window = w;
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    /// Freezes the visible window, or follows the incoming data again if already paused.
+    pub fn toggle_pause(&mut self) {
+        let paused_at = match self.paused_at {
+            Some(_) => None,
+            None => Some(self.now()),
+        };
+        self.set_paused(paused_at);
+    }
+
+    /// Freezes the visible window to end at `paused_at`, or follows the incoming data if `None`.
+    fn set_paused(&mut self, paused_at: Option<f64>) {
+        self.paused_at = paused_at;
+        self.snapshot = match paused_at {
+            Some(_) => self.series.clone(),
+            None => Vec::new(),
+        };
+    }
+
+    pub fn status(&self) -> Option<&str> {
+        self.status.as_deref()
+    }
+
+    pub fn set_status(&mut self, status: String) {
+        self.status = Some(status);
+    }
+
+    /// Writes the visible window as CSV, with one column per series.
+    pub fn export_csv(&self, path: &Path) -> io::Result<()> {
+        let mut file = std::fs::File::create(path)?;
+        self.write_csv(&mut file)?;
+        file.flush()
+    }
+
+    fn write_csv<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let visible = self.visible(self.bounds());
+
+        write!(out, "time")?;
+        for series in &visible {
+            write!(out, ",{}", series.name)?;
+        }
+        writeln!(out)?;
+
+        // The n-th samples of each series received at the same time end up in the same row.
+        let mut rows: BTreeMap<(u64, usize), Vec<Option<f64>>> = BTreeMap::new();
+        for (i, series) in visible.iter().enumerate() {
+            let mut previous = None;
+            let mut n = 0;
+            for &(t, value) in &series.points {
+                n = if previous == Some(t) { n + 1 } else { 0 };
+                previous = Some(t);
+                rows.entry((t.to_bits(), n))
+                    .or_insert_with(|| vec![None; visible.len()])[i] = Some(value);
+            }
+        }
+
+        for ((t, _), values) in rows {
+            write!(out, "{:.6}", f64::from_bits(t))?;
+            for value in values {
+                match value {
+                    Some(value) => write!(out, ",{}", value)?,
+                    None => write!(out, ",")?,
+                }
+            }
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns all `key=value` pairs in `line` whose value is a number.
+fn parse_key_values(line: &str) -> impl Iterator<Item = (&str, f64)> {
+    line.split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+        .filter_map(|token| {
+            let (key, value) = token.split_once('=')?;
+            let value = value.parse::<f64>().ok()?;
+            (!key.is_empty()).then_some((key, value))
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PlotConfig, PlotState, SampleFormat, MAX_PENDING};
+
+    fn plot(samples: SampleFormat, series: &[&str]) -> PlotState {
+        PlotState::new(PlotConfig {
+            samples,
+            series: series.iter().map(|s| s.to_string()).collect(),
+            window: 10.0,
+        })
+    }
+
+    #[test]
+    fn key_value_lines() {
+        let mut plot = plot(SampleFormat::KeyValue, &[]);
+        plot.ingest_at(b"temp=21.5 humidity=40, note=hi\nte", 1.0);
+        plot.ingest_at(b"mp=22\n", 2.0);
+
+        let visible = plot.visible([0.0, 10.0]);
+        assert_eq!(visible.len(), 2);
+        assert_eq!(visible[0].name, "temp");
+        assert_eq!(visible[0].points, vec![(1.0, 21.5), (2.0, 22.0)]);
+        assert_eq!(visible[1].name, "humidity");
+        assert_eq!(visible[1].points, vec![(1.0, 40.0)]);
+    }
+
+    #[test]
+    fn key_value_series_filter() {
+        let mut plot = plot(SampleFormat::KeyValue, &["b"]);
+        plot.ingest_at(b"a=1 b=2\n", 1.0);
+
+        let visible = plot.visible([0.0, 10.0]);
+        assert_eq!(visible.len(), 1);
+        assert_eq!(visible[0].points, vec![(1.0, 2.0)]);
+    }
+
+    #[test]
+    fn binary_records() {
+        let mut plot = plot(SampleFormat::I16, &["x", "y"]);
+        plot.ingest_at(&[0x01, 0x00, 0xff, 0xff, 0x02], 1.0);
+        plot.ingest_at(&[0x00, 0x03, 0x00], 2.0);
+
+        let visible = plot.visible([0.0, 10.0]);
+        assert_eq!(visible[0].points, vec![(1.0, 1.0), (2.0, 2.0)]);
+        assert_eq!(visible[1].points, vec![(1.0, -1.0), (2.0, 3.0)]);
+    }
+
+    #[test]
+    fn pending_limit() {
+        let mut plot = plot(SampleFormat::KeyValue, &[]);
+        plot.ingest_at(&[b'x'; MAX_PENDING + 1], 1.0);
+        assert!(plot.pending.is_empty());
+
+        plot.ingest_at(b"a=1\n", 2.0);
+        assert_eq!(plot.visible([0.0, 10.0])[0].points, vec![(2.0, 1.0)]);
+    }
+
+    #[test]
+    fn paused_history() {
+        let mut plot = plot(SampleFormat::KeyValue, &[]);
+        plot.ingest_at(b"a=1\n", 1.0);
+        plot.set_paused(Some(2.0));
+        plot.ingest_at(b"a=2\n", 1000.0);
+        assert_eq!(
+            plot.visible([0.0, 2.0])[0].points,
+            vec![(1.0, 1.0)],
+            "points of the paused window must be kept"
+        );
+        assert_eq!(
+            plot.series[0].points,
+            vec![(1000.0, 2.0)],
+            "history must be bounded while paused"
+        );
+
+        plot.set_paused(None);
+        plot.ingest_at(b"a=3\n", 1001.0);
+        assert_eq!(
+            plot.visible([0.0, 1001.0])[0].points,
+            vec![(1000.0, 2.0), (1001.0, 3.0)]
+        );
+    }
+
+    #[test]
+    fn received_time() {
+        // Data of a recording, received long ago.
+        let mut plot = plot(SampleFormat::KeyValue, &[]);
+        let received = chrono::Local::now() - chrono::Duration::hours(5);
+        plot.ingest(b"a=1\n", received);
+        plot.ingest(b"a=2\n", received + chrono::Duration::seconds(2));
+
+        let points = &plot.series[0].points;
+        assert!((points[1].0 - points[0].0 - 2.0).abs() < 1e-6);
+        assert!((plot.now() - points[1].0).abs() < 1.0);
+    }
+
+    #[test]
+    fn csv_export() {
+        let mut plot = plot(SampleFormat::KeyValue, &[]);
+        plot.ingest_at(b"a=1 b=2\na=3\n", 0.5);
+        plot.set_paused(Some(1.0));
+
+        let mut csv = Vec::new();
+        plot.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "time,a,b\n0.500000,1,2\n0.500000,3,\n"
+        );
+    }
+}
@@ -0,0 +1,291 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Local};
+
+use super::{
+    channel::DataFormat,
+    plot::PlotConfig,
+    source::{Received, Target, UpSource},
+    status::SessionInfo,
+};
+
+/// The version of the recording format, increased on incompatible changes.
+const VERSION: u32 = 1;
+
+/// An up channel of a recording.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RecordedChannel {
+    pub name: String,
+    pub format: DataFormat,
+    /// The size of the buffer on the target, for the statistics in the status bar.
+    pub buffer_size: usize,
+    pub plot: Option<PlotConfig>,
+    /// The core whose RTT control block has the channel, 0 in recordings made before it was
+    /// recorded.
+    #[serde(default)]
+    pub core: usize,
+}
+
+/// The first line of a recording.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Header {
+    version: u32,
+    /// When the recording started, in RFC 3339 format.
+    start: String,
+    session: SessionInfo,
+    channels: Vec<RecordedChannel>,
+}
+
+/// The data of one read from an up channel.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct Record {
+    /// Microseconds since the start of the recording.
+    time_us: u64,
+    /// The index of the channel in the header.
+    channel: usize,
+    /// The data, hex encoded.
+    data: String,
+}
+
+/// Writes the raw data received on up channels to a recording.
+///
+/// A recording is a JSON lines file: a header describing the session and the channels, followed
+/// by one line for every read which returned data.
+#[derive(Debug)]
+pub struct Recorder {
+    file: BufWriter<File>,
+    start: DateTime<Local>,
+}
+
+impl Recorder {
+    pub fn create(
+        path: &Path,
+        session: &SessionInfo,
+        channels: Vec<RecordedChannel>,
+    ) -> io::Result<Self> {
+        let start = Local::now();
+        let mut file = BufWriter::new(File::create(path)?);
+        let header = Header {
+            version: VERSION,
+            start: start.to_rfc3339(),
+            session: session.clone(),
+            channels,
+        };
+        serde_json::to_writer(&mut file, &header)?;
+        writeln!(file)?;
+
+        Ok(Self { file, start })
+    }
+
+    /// Records `data` received at `time` on the channel with index `channel` in the header.
+    pub fn record(&mut self, channel: usize, time: DateTime<Local>, data: &[u8]) -> io::Result<()> {
+        let record = Record {
+            time_us: (time - self.start)
+                .num_microseconds()
+                .unwrap_or_default()
+                .max(0) as u64,
+            channel,
+            data: data.iter().fold(String::new(), |mut output, byte| {
+                let _ = write!(output, "{:02x}", byte);
+                output
+            }),
+        };
+        serde_json::to_writer(&mut self.file, &record)?;
+        writeln!(self.file)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// A recording loaded for replaying it.
+#[derive(Debug)]
+pub struct Recording {
+    pub start: DateTime<Local>,
+    pub session: SessionInfo,
+    pub channels: Vec<RecordedChannel>,
+    /// The time since the start, channel index and data of every read.
+    records: Vec<(Duration, usize, Vec<u8>)>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path)
+            .with_context(|| format!("Could not open the recording {}", path.display()))?;
+        Self::read(BufReader::new(file))
+            .with_context(|| format!("Could not read the recording {}", path.display()))
+    }
+
+    pub fn read(reader: impl BufRead) -> Result<Self> {
+        let mut lines = reader.lines();
+        let header = lines
+            .next()
+            .ok_or_else(|| anyhow!("The recording is empty"))??;
+        let header: Header = serde_json::from_str(&header)?;
+        if header.version != VERSION {
+            bail!(
+                "Recordings of version {} are not supported, only version {}",
+                header.version,
+                VERSION
+            );
+        }
+        let start = DateTime::parse_from_rfc3339(&header.start)?.with_timezone(&Local);
+
+        let mut records = Vec::new();
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: Record = serde_json::from_str(&line)
+                .with_context(|| format!("Invalid record in line {}", i + 2))?;
+            if record.channel >= header.channels.len() {
+                bail!("Unknown channel {} in line {}", record.channel, i + 2);
+            }
+            let data =
+                parse_hex(&record.data).ok_or_else(|| anyhow!("Invalid data in line {}", i + 2))?;
+            records.push((Duration::from_micros(record.time_us), record.channel, data));
+        }
+
+        Ok(Self {
+            start,
+            session: header.session,
+            channels: header.channels,
+            records,
+        })
+    }
+
+    /// Splits the recording into its channels, which play back their data `speed` times as fast
+    /// as it was received, starting now. A speed of 0 plays back everything at once.
+    pub fn into_channels(self, speed: f64) -> Vec<(RecordedChannel, ReplayChannel)> {
+        let started = Instant::now();
+        let start = self.start;
+        let mut channels = self
+            .channels
+            .into_iter()
+            .map(|channel| {
+                let replay = ReplayChannel {
+                    records: VecDeque::new(),
+                    start,
+                    started,
+                    speed,
+                    buffer_size: channel.buffer_size,
+                };
+                (channel, replay)
+            })
+            .collect::<Vec<_>>();
+        for (time, channel, data) in self.records {
+            channels[channel].1.records.push_back((time, data));
+        }
+        channels
+    }
+}
+
+/// Plays back the recorded data of a channel at the pace it was received.
+#[derive(Debug)]
+pub struct ReplayChannel {
+    records: VecDeque<(Duration, Vec<u8>)>,
+    /// When the recording started.
+    start: DateTime<Local>,
+    /// When the replay started.
+    started: Instant,
+    speed: f64,
+    buffer_size: usize,
+}
+
+impl UpSource for ReplayChannel {
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    /// Returns the data which is due by now, one entry per recorded read, together with the time
+    /// it was originally received.
+    fn read(&mut self, _target: &mut Target) -> Result<Vec<Received>> {
+        let elapsed = if self.speed > 0.0 {
+            self.started.elapsed().as_secs_f64() * self.speed
+        } else {
+            f64::INFINITY
+        };
+        let mut due = Vec::new();
+        while self
+            .records
+            .front()
+            .is_some_and(|(time, _)| time.as_secs_f64() <= elapsed)
+        {
+            if let Some((time, data)) = self.records.pop_front() {
+                let time = self.start
+                    + chrono::Duration::from_std(time).unwrap_or_else(|_| chrono::Duration::zero());
+                due.push(Received { time, data });
+            }
+        }
+        Ok(due)
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [high, low] => u8::from_str_radix(std::str::from_utf8(&[*high, *low]).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{RecordedChannel, Recorder, Recording};
+    use crate::rttui::{
+        channel::DataFormat,
+        source::{Target, UpSource},
+        status::SessionInfo,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn record_and_replay() {
+        let path = std::env::temp_dir().join(format!(
+            "cargo-embed-recording-{}.jsonl",
+            std::process::id()
+        ));
+        let channel = RecordedChannel {
+            name: "defmt".to_owned(),
+            format: DataFormat::Defmt,
+            buffer_size: 1024,
+            plot: None,
+            core: 1,
+        };
+        let mut recorder = Recorder::create(&path, &SessionInfo::default(), vec![channel]).unwrap();
+        let now = chrono::Local::now();
+        recorder.record(0, now, &[0x01, 0xab]).unwrap();
+        recorder.record(0, now, b"later").unwrap();
+        recorder.flush().unwrap();
+
+        let recording = Recording::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(recording.channels[0].name, "defmt");
+        assert_eq!(recording.channels[0].core, 1);
+        assert_eq!(recording.records.len(), 2);
+        assert!(recording.records[0].0 < Duration::from_secs(1));
+
+        let mut channels = recording.into_channels(0.0);
+        let due = channels[0].1.read(&mut Target::new(None)).unwrap();
+        assert_eq!(due[0].data, [0x01, 0xab]);
+        assert_eq!(due[1].data, b"later");
+
+        assert!(Recording::read(&b"{\"version\":2}"[..]).is_err());
+    }
+}
@@ -0,0 +1,288 @@
+use std::collections::VecDeque;
+
+/// Limits how much history is kept for a channel.
+///
+/// The oldest lines are dropped as soon as either limit is exceeded.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ScrollbackLimit {
+    /// The maximum number of lines.
+    pub lines: usize,
+    /// The maximum number of bytes of text.
+    pub bytes: usize,
+}
+
+impl Default for ScrollbackLimit {
+    /// Keeps everything.
+    fn default() -> Self {
+        Self {
+            lines: usize::MAX,
+            bytes: usize::MAX,
+        }
+    }
+}
+
+/// A line of the history of a channel.
+#[derive(Debug, Clone)]
+pub struct Line {
+    /// The polling round in which the line was received, used to merge channels in receive order.
+    pub round: u64,
+    pub text: String,
+    /// For lines of the merged tab, the index of the tab the line was received on.
+    pub tab: Option<usize>,
+    /// Whether this is a marker inserted by the user instead of a received line.
+    pub marker: bool,
+}
+
+/// A row of a wrapped line, as shown on the screen.
+#[derive(Debug)]
+pub struct Row<'a> {
+    pub line: &'a Line,
+    /// Whether this is the first row of the line.
+    pub first: bool,
+    pub text: &'a str,
+}
+
+/// The history of a channel, bounded by a [ScrollbackLimit].
+///
+/// Lines are wrapped lazily and the wrapped rows are cached for the last width, so only new
+/// lines have to be wrapped unless the terminal is resized.
+#[derive(Debug, Default)]
+pub struct Scrollback {
+    lines: VecDeque<Line>,
+    limit: ScrollbackLimit,
+    /// The number of bytes of text in `lines`.
+    bytes: usize,
+    /// The number of lines dropped to stay within the limit.
+    dropped: usize,
+    /// The rows of the first `wrapped.len()` lines, wrapped to `wrap_width`.
+    wrapped: VecDeque<Vec<String>>,
+    wrap_width: usize,
+    /// The number of rows in `wrapped`.
+    rows: usize,
+}
+
+impl Scrollback {
+    pub fn set_limit(&mut self, limit: ScrollbackLimit) {
+        self.limit = limit;
+        self.enforce_limit();
+    }
+
+    pub fn limit(&self) -> ScrollbackLimit {
+        self.limit
+    }
+
+    pub fn push(&mut self, line: Line) {
+        self.bytes += line.text.len();
+        self.lines.push_back(line);
+        self.enforce_limit();
+    }
+
+    /// Removes the newest line, e.g. to continue it with newly received text.
+    pub fn pop_back(&mut self) -> Option<Line> {
+        let line = self.lines.pop_back()?;
+        self.bytes -= line.text.len();
+        if self.wrapped.len() > self.lines.len() {
+            if let Some(rows) = self.wrapped.pop_back() {
+                self.rows -= rows.len();
+            }
+        }
+        Some(line)
+    }
+
+    fn enforce_limit(&mut self) {
+        while self.lines.len() > self.limit.lines || self.bytes > self.limit.bytes {
+            let line = match self.lines.pop_front() {
+                Some(line) => line,
+                None => break,
+            };
+            self.bytes -= line.text.len();
+            self.dropped += 1;
+            if let Some(rows) = self.wrapped.pop_front() {
+                self.rows -= rows.len();
+            }
+        }
+    }
+
+    pub fn lines(&self) -> impl DoubleEndedIterator<Item = &Line> {
+        self.lines.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// The number of lines dropped since the history was last cleared.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self {
+            limit: self.limit,
+            ..Self::default()
+        };
+    }
+
+    /// Wraps all lines to `width` columns, minus the indentation `indent` returns for a line.
+    ///
+    /// `indent` has to return the same value for a line every time it is called.
+    /// Returns the total number of rows.
+    pub fn wrap(&mut self, width: usize, indent: impl Fn(&Line) -> usize) -> usize {
+        if width != self.wrap_width {
+            self.wrapped.clear();
+            self.rows = 0;
+            self.wrap_width = width;
+        }
+
+        for line in self.lines.iter().skip(self.wrapped.len()) {
+            let rows = textwrap::wrap(&line.text, width.saturating_sub(indent(line)).max(1))
+                .into_iter()
+                .map(|row| row.into_owned())
+                .collect::<Vec<_>>();
+            self.rows += rows.len();
+            self.wrapped.push_back(rows);
+        }
+
+        self.rows
+    }
+
+    /// Returns the indices of the marker lines.
+    pub fn markers(&self) -> impl Iterator<Item = usize> + '_ {
+        self.lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| line.marker)
+            .map(|(i, _)| i)
+    }
+
+    /// Returns the number of rows from the first row of line `index` to the last row, as
+    /// wrapped by the last call to [Scrollback::wrap].
+    pub fn rows_from(&self, index: usize) -> usize {
+        self.wrapped.iter().skip(index).map(Vec::len).sum()
+    }
+
+    /// Returns up to `height` rows, ending `offset` rows above the last one.
+    ///
+    /// Only returns rows of lines wrapped by the last call to [Scrollback::wrap].
+    pub fn rows(&self, offset: usize, height: usize) -> Vec<Row<'_>> {
+        let mut rows = Vec::with_capacity(height);
+        let mut skip = offset;
+
+        'lines: for (line, wrapped) in self.lines.iter().zip(&self.wrapped).rev() {
+            if skip >= wrapped.len() {
+                skip -= wrapped.len();
+                continue;
+            }
+            for (n, text) in wrapped.iter().enumerate().rev().skip(skip) {
+                if rows.len() == height {
+                    break 'lines;
+                }
+                rows.push(Row {
+                    line,
+                    first: n == 0,
+                    text,
+                });
+            }
+            skip = 0;
+        }
+
+        rows.reverse();
+        rows
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Line, Scrollback, ScrollbackLimit};
+
+    fn line(text: &str) -> Line {
+        Line {
+            round: 0,
+            text: text.to_owned(),
+            tab: None,
+            marker: false,
+        }
+    }
+
+    fn texts(scrollback: &Scrollback, offset: usize, height: usize) -> Vec<&str> {
+        scrollback
+            .rows(offset, height)
+            .into_iter()
+            .map(|row| row.text)
+            .collect()
+    }
+
+    #[test]
+    fn limits() {
+        let mut scrollback = Scrollback::default();
+        scrollback.set_limit(ScrollbackLimit {
+            lines: 3,
+            bytes: 10,
+        });
+
+        for text in ["a", "b", "c", "d"] {
+            scrollback.push(line(text));
+        }
+        assert_eq!(scrollback.len(), 3);
+        assert_eq!(scrollback.dropped(), 1);
+
+        scrollback.push(line("0123456789"));
+        assert_eq!(scrollback.len(), 1);
+        assert_eq!(scrollback.dropped(), 4);
+
+        scrollback.clear();
+        assert_eq!(scrollback.dropped(), 0);
+    }
+
+    #[test]
+    fn wrap_cache() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push(line("one two"));
+        scrollback.push(line("three"));
+
+        assert_eq!(scrollback.wrap(5, |_| 0), 3);
+        assert_eq!(texts(&scrollback, 0, 10), ["one", "two", "three"]);
+        assert_eq!(texts(&scrollback, 1, 2), ["one", "two"]);
+
+        // Continuing the last line invalidates its rows only.
+        let mut last = scrollback.pop_back().unwrap();
+        last.text += " four";
+        scrollback.push(last);
+        assert_eq!(scrollback.wrap(5, |_| 0), 4);
+        assert_eq!(texts(&scrollback, 0, 2), ["three", "four"]);
+
+        assert_eq!(scrollback.wrap(20, |_| 0), 2);
+        assert_eq!(texts(&scrollback, 0, 10), ["one two", "three four"]);
+    }
+
+    #[test]
+    fn markers() {
+        let mut scrollback = Scrollback::default();
+        scrollback.push(line("one two"));
+        scrollback.push(Line {
+            marker: true,
+            ..line("marker")
+        });
+        scrollback.push(line("three four"));
+        scrollback.wrap(6, |_| 0);
+
+        assert_eq!(scrollback.markers().collect::<Vec<_>>(), [1]);
+        assert_eq!(scrollback.rows_from(1), 3);
+        assert_eq!(scrollback.rows_from(0), 5);
+    }
+
+    #[test]
+    fn dropped_lines_leave_the_cache() {
+        let mut scrollback = Scrollback::default();
+        scrollback.set_limit(ScrollbackLimit {
+            lines: 1,
+            bytes: usize::MAX,
+        });
+        scrollback.push(line("one two"));
+        assert_eq!(scrollback.wrap(3, |_| 0), 2);
+
+        scrollback.push(line("six"));
+        assert_eq!(scrollback.wrap(3, |_| 0), 1);
+        assert_eq!(texts(&scrollback, 0, 10), ["six"]);
+    }
+}
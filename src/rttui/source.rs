@@ -0,0 +1,198 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use probe_rs::Core;
+use probe_rs_rtt::{DownChannel, UpChannel};
+
+/// Data received on an up channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Received {
+    pub time: DateTime<Local>,
+    pub data: Vec<u8>,
+}
+
+/// Where the data of an up channel comes from.
+///
+/// Sources which read from a target get access to it passed, others can ignore it. This lets the
+/// UI run without a probe, e.g. to replay a recording or in tests.
+pub trait UpSource: fmt::Debug {
+    fn name(&self) -> Option<&str>;
+
+    /// The size of the buffer on the target, for the statistics in the status bar.
+    fn buffer_size(&self) -> usize;
+
+    /// Returns the data which arrived since the last call, one entry per read of the target.
+    ///
+    /// A read of a target returns everything in its buffer, so the size of an entry is the fill
+    /// level of the buffer at the time of the read.
+    fn read(&mut self, target: &mut Target) -> Result<Vec<Received>>;
+}
+
+/// Where the data of a down channel goes to.
+pub trait DownSink: fmt::Debug {
+    fn name(&self) -> Option<&str>;
+
+    /// Writes as much of `data` as currently fits and returns the number of bytes written.
+    fn write(&mut self, target: &mut Target, data: &[u8]) -> Result<usize>;
+}
+
+/// Access to the target channels are read from and written to, if there is one.
+pub struct Target<'a, 'probe> {
+    core: Option<&'a mut Core<'probe>>,
+}
+
+impl<'a, 'probe> Target<'a, 'probe> {
+    /// The target attached with `core`, or none, e.g. while a recording is replayed.
+    pub fn new(core: Option<&'a mut Core<'probe>>) -> Self {
+        Self { core }
+    }
+
+    fn core(&mut self) -> Option<&mut Core<'probe>> {
+        self.core.as_deref_mut()
+    }
+}
+
+/// An up channel of a target, read through a buffer of the size of the channel's buffer.
+#[derive(Debug)]
+pub struct RttUpChannel {
+    channel: UpChannel,
+    /// Kept between reads, so the channels don't allocate on every poll.
+    buffer: Vec<u8>,
+}
+
+impl RttUpChannel {
+    pub fn new(channel: UpChannel) -> Self {
+        let buffer = vec![0u8; channel.buffer_size()];
+        Self { channel, buffer }
+    }
+}
+
+impl UpSource for RttUpChannel {
+    fn name(&self) -> Option<&str> {
+        self.channel.name()
+    }
+
+    fn buffer_size(&self) -> usize {
+        self.channel.buffer_size()
+    }
+
+    fn read(&mut self, target: &mut Target) -> Result<Vec<Received>> {
+        let core = match target.core() {
+            Some(core) => core,
+            None => return Ok(Vec::new()),
+        };
+
+        // The whole buffer is read at once, which empties it as far as the target has written.
+        let buffer = &mut self.buffer;
+        let count = self.channel.read(core, buffer)?;
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+        Ok(vec![Received {
+            time: Local::now(),
+            data: buffer[..count].to_vec(),
+        }])
+    }
+}
+
+impl DownSink for DownChannel {
+    fn name(&self) -> Option<&str> {
+        DownChannel::name(self)
+    }
+
+    fn write(&mut self, target: &mut Target, data: &[u8]) -> Result<usize> {
+        match target.core() {
+            Some(core) => Ok(DownChannel::write(self, core, data)?),
+            None => Ok(0),
+        }
+    }
+}
+
+/// A channel in memory, which returns the data pushed into it and keeps the data written to it.
+///
+/// It shows output which doesn't come from RTT, like semihosting and SWO, and stands in for a
+/// target in tests. Clones share the same data, so a clone can feed a channel which is owned by
+/// the UI.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryChannel {
+    name: Option<String>,
+    buffer_size: usize,
+    state: Arc<Mutex<MemoryState>>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    received: VecDeque<Received>,
+    written: Vec<u8>,
+    /// How many more bytes can be written before the buffer is full, unlimited if `None`.
+    free: Option<usize>,
+}
+
+impl MemoryChannel {
+    pub fn new(name: Option<String>, buffer_size: usize) -> Self {
+        Self {
+            name,
+            buffer_size,
+            state: Arc::default(),
+        }
+    }
+
+    /// Adds data, returned by the next read together with the current time.
+    pub fn push(&self, data: &[u8]) {
+        self.state.lock().unwrap().received.push_back(Received {
+            time: Local::now(),
+            data: data.to_vec(),
+        });
+    }
+
+    /// Returns all data written to the channel so far.
+    #[cfg(test)]
+    pub fn written(&self) -> Vec<u8> {
+        self.state.lock().unwrap().written.clone()
+    }
+
+    /// Limits how many more bytes can be written, as if the target stopped reading.
+    #[cfg(test)]
+    pub fn set_free(&self, free: Option<usize>) {
+        self.state.lock().unwrap().free = free;
+    }
+}
+
+impl UpSource for MemoryChannel {
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn buffer_size(&self) -> usize {
+        self.buffer_size
+    }
+
+    fn read(&mut self, _target: &mut Target) -> Result<Vec<Received>> {
+        Ok(self.state.lock().unwrap().received.drain(..).collect())
+    }
+}
+
+impl DownSink for MemoryChannel {
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn write(&mut self, _target: &mut Target, data: &[u8]) -> Result<usize> {
+        // Like on a target, at most one buffer can be written at once.
+        let mut state = self.state.lock().unwrap();
+        let count = data
+            .len()
+            .min(self.buffer_size)
+            .min(state.free.unwrap_or(usize::MAX));
+        if let Some(free) = state.free.as_mut() {
+            *free -= count;
+        }
+        state.written.extend_from_slice(&data[..count]);
+        Ok(count)
+    }
+}
@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use probe_rs::CoreStatus;
+
+/// How often the statistics in the status bar are updated.
+pub const STATUS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Information about the debug session, shown in the status bar.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+pub struct SessionInfo {
+    pub chip: String,
+    pub probe: String,
+    pub protocol: String,
+    pub speed_khz: u32,
+}
+
+/// Throughput and buffer statistics of an up channel.
+#[derive(Debug, Default)]
+pub struct ChannelStats {
+    /// Bytes received since the last update.
+    bytes: usize,
+    /// The highest fill level of the target buffer seen since the last update.
+    peak_fill: usize,
+    /// Bytes per second over the last interval.
+    throughput: f64,
+    /// The highest fill level of the target buffer during the last interval, in percent.
+    fill: usize,
+    /// How often the target buffer was found full. Depending on the channel mode, the target
+    /// dropped data or blocked in that case.
+    overflows: usize,
+}
+
+impl ChannelStats {
+    /// Records a read of `count` bytes from a target buffer of `buffer_size` bytes.
+    ///
+    /// As a read returns everything available, see [super::source::UpSource::read], `count` is the
+    /// fill level of the buffer before the read.
+    pub fn record(&mut self, count: usize, buffer_size: usize) {
+        self.bytes += count;
+        self.peak_fill = self.peak_fill.max(count);
+        // A ring buffer can only hold one byte less than its size.
+        if buffer_size > 1 && count >= buffer_size - 1 {
+            self.overflows += 1;
+        }
+    }
+
+    /// Computes the statistics of the interval which ended after `elapsed`.
+    pub fn update(&mut self, elapsed: Duration, buffer_size: usize) {
+        self.throughput = self.bytes as f64 / elapsed.as_secs_f64();
+        self.fill = if buffer_size > 1 {
+            (self.peak_fill * 100 / (buffer_size - 1)).min(100)
+        } else {
+            0
+        };
+        self.bytes = 0;
+        self.peak_fill = 0;
+    }
+}
+
+/// Formats the contents of the status bar.
+pub fn status_line<'a>(
+    info: &SessionInfo,
+    core_status: Option<CoreStatus>,
+    logging: bool,
+    channels: impl IntoIterator<Item = (&'a str, &'a ChannelStats)>,
+) -> String {
+    let core = match core_status {
+        Some(CoreStatus::Running) => "running",
+        Some(CoreStatus::Halted(_)) => "halted",
+        Some(CoreStatus::LockedUp) => "locked up",
+        Some(CoreStatus::Sleeping) => "sleeping",
+        Some(CoreStatus::Unknown) | None => "unknown",
+    };
+
+    let mut line = format!(
+        " {} | {} {} {} kHz | Core {} | Log {}",
+        info.chip,
+        info.probe,
+        info.protocol,
+        info.speed_khz,
+        core,
+        if logging { "on" } else { "off" }
+    );
+    for (name, stats) in channels {
+        line += &format!(
+            " | {} {} {}%",
+            name,
+            format_rate(stats.throughput),
+            stats.fill
+        );
+        if stats.overflows > 0 {
+            line += &format!(" full {}x", stats.overflows);
+        }
+    }
+    line
+}
+
+fn format_rate(bytes_per_second: f64) -> String {
+    if bytes_per_second >= 1000.0 {
+        format!("{:.1} kB/s", bytes_per_second / 1000.0)
+    } else {
+        format!("{:.0} B/s", bytes_per_second)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{status_line, ChannelStats, SessionInfo};
+    use probe_rs::CoreStatus;
+    use std::time::Duration;
+
+    #[test]
+    fn channel_statistics() {
+        let mut stats = ChannelStats::default();
+        stats.record(511, 1024);
+        stats.record(1023, 1024);
+        stats.update(Duration::from_millis(500), 1024);
+
+        let info = SessionInfo {
+            chip: "nRF52840_xxAA".to_owned(),
+            probe: "J-Link".to_owned(),
+            protocol: "SWD".to_owned(),
+            speed_khz: 4000,
+        };
+        assert_eq!(
+            status_line(
+                &info,
+                Some(CoreStatus::Running),
+                true,
+                [("defmt", &stats)]
+            ),
+            " nRF52840_xxAA | J-Link SWD 4000 kHz | Core running | Log on | defmt 3.1 kB/s 100% full 1x"
+        );
+
+        stats.update(Duration::from_secs(1), 1024);
+        assert_eq!(
+            status_line(&info, None, false, [("defmt", &stats)]),
+            " nRF52840_xxAA | J-Link SWD 4000 kHz | Core unknown | Log off | defmt 0 B/s 0% full 1x"
+        );
+    }
+}
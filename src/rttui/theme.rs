@@ -0,0 +1,293 @@
+use std::convert::TryFrom;
+
+use tui::style::{Color, Modifier, Style};
+
+/// The built-in color themes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum BuiltinTheme {
+    /// For terminals with a dark background.
+    Dark,
+    /// For terminals with a light background.
+    Light,
+    /// Black and white with a palette which stays distinguishable with color blindness.
+    HighContrast,
+}
+
+/// A color, written as a name like `Yellow` or `LightBlue`, as `#rrggbb` or as an index into
+/// the 256 color palette of the terminal.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ThemeColor(Color);
+
+impl TryFrom<String> for ThemeColor {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let color = match s.to_ascii_lowercase().as_str() {
+            "reset" => Color::Reset,
+            "black" => Color::Black,
+            "red" => Color::Red,
+            "green" => Color::Green,
+            "yellow" => Color::Yellow,
+            "blue" => Color::Blue,
+            "magenta" => Color::Magenta,
+            "cyan" => Color::Cyan,
+            "gray" => Color::Gray,
+            "darkgray" => Color::DarkGray,
+            "lightred" => Color::LightRed,
+            "lightgreen" => Color::LightGreen,
+            "lightyellow" => Color::LightYellow,
+            "lightblue" => Color::LightBlue,
+            "lightmagenta" => Color::LightMagenta,
+            "lightcyan" => Color::LightCyan,
+            "white" => Color::White,
+            name => match name.strip_prefix('#') {
+                Some(hex) if hex.len() == 6 => {
+                    let rgb = u32::from_str_radix(hex, 16)
+                        .map_err(|_| format!("Invalid color '{}'", s))?;
+                    Color::Rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+                }
+                _ => Color::Indexed(name.parse().map_err(|_| format!("Invalid color '{}'", s))?),
+            },
+        };
+        Ok(Self(color))
+    }
+}
+
+impl From<ThemeColor> for String {
+    fn from(color: ThemeColor) -> Self {
+        match color.0 {
+            Color::Rgb(r, g, b) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+            Color::Indexed(i) => i.to_string(),
+            color => format!("{:?}", color),
+        }
+    }
+}
+
+/// Overrides the colors of a single element of the UI.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColorOverride {
+    pub fg: Option<ThemeColor>,
+    pub bg: Option<ThemeColor>,
+}
+
+/// The theme config of the RTT UI.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeConfig {
+    /// The built-in theme, whose colors can be overridden with the other options.
+    pub base: BuiltinTheme,
+    pub tab_bar: Option<ColorOverride>,
+    pub selected_tab: Option<ColorOverride>,
+    pub status_bar: Option<ColorOverride>,
+    pub input: Option<ColorOverride>,
+    pub inactive_input: Option<ColorOverride>,
+    pub notice: Option<ColorOverride>,
+    pub border: Option<ColorOverride>,
+    pub marker: Option<ColorOverride>,
+    /// Colors used to tell plot series and channels in the merged tab apart.
+    pub palette: Option<Vec<ThemeColor>>,
+}
+
+/// The styles of all elements of the RTT UI.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub tab_bar: Style,
+    pub selected_tab: Style,
+    pub status_bar: Style,
+    /// The input line of the focused tab, also used for the status line of plots.
+    pub input: Style,
+    pub inactive_input: Style,
+    /// Errors and other messages shown in place of the input line.
+    pub notice: Style,
+    /// The border of the focused pane.
+    pub border: Style,
+    /// Secondary information, like the number of dropped lines.
+    pub dim: Style,
+    /// Marker lines inserted by the user.
+    pub marker: Style,
+    pub palette: Vec<Color>,
+}
+
+impl Theme {
+    pub fn builtin(theme: BuiltinTheme) -> Self {
+        let style = |fg, bg| Style::default().fg(fg).bg(bg);
+        match theme {
+            BuiltinTheme::Dark => Self {
+                tab_bar: style(Color::Black, Color::Yellow),
+                selected_tab: style(Color::Green, Color::Yellow).add_modifier(Modifier::BOLD),
+                status_bar: style(Color::Black, Color::Yellow),
+                input: style(Color::Yellow, Color::Blue),
+                inactive_input: style(Color::Gray, Color::DarkGray),
+                notice: style(Color::White, Color::Red),
+                border: Style::default().fg(Color::Yellow),
+                dim: Style::default()
+                    .fg(Color::DarkGray)
+                    .add_modifier(Modifier::ITALIC),
+                marker: style(Color::Black, Color::Cyan),
+                palette: vec![
+                    Color::Cyan,
+                    Color::Yellow,
+                    Color::Magenta,
+                    Color::Green,
+                    Color::Red,
+                    Color::Blue,
+                ],
+            },
+            BuiltinTheme::Light => Self {
+                tab_bar: style(Color::White, Color::Blue),
+                selected_tab: style(Color::Yellow, Color::Blue).add_modifier(Modifier::BOLD),
+                status_bar: style(Color::White, Color::Blue),
+                input: style(Color::Black, Color::LightCyan),
+                inactive_input: style(Color::Black, Color::Gray),
+                notice: style(Color::White, Color::Red),
+                border: Style::default().fg(Color::Blue),
+                dim: Style::default()
+                    .fg(Color::Gray)
+                    .add_modifier(Modifier::ITALIC),
+                marker: style(Color::White, Color::Magenta),
+                palette: vec![
+                    Color::Blue,
+                    Color::Magenta,
+                    Color::Red,
+                    Color::Green,
+                    Color::DarkGray,
+                    Color::Black,
+                ],
+            },
+            BuiltinTheme::HighContrast => Self {
+                tab_bar: style(Color::White, Color::Black),
+                selected_tab: style(Color::Black, Color::White).add_modifier(Modifier::BOLD),
+                status_bar: style(Color::Black, Color::White),
+                input: style(Color::Black, Color::White),
+                inactive_input: style(Color::White, Color::Black),
+                notice: style(Color::White, Color::Red).add_modifier(Modifier::BOLD),
+                border: Style::default()
+                    .fg(Color::White)
+                    .add_modifier(Modifier::BOLD),
+                dim: Style::default().add_modifier(Modifier::ITALIC),
+                marker: style(Color::Black, Color::White).add_modifier(Modifier::BOLD),
+                // The palette of Okabe and Ito.
+                palette: vec![
+                    Color::Rgb(0xe6, 0x9f, 0x00),
+                    Color::Rgb(0x56, 0xb4, 0xe9),
+                    Color::Rgb(0x00, 0x9e, 0x73),
+                    Color::Rgb(0xf0, 0xe4, 0x42),
+                    Color::Rgb(0x00, 0x72, 0xb2),
+                    Color::Rgb(0xd5, 0x5e, 0x00),
+                    Color::Rgb(0xcc, 0x79, 0xa7),
+                ],
+            },
+        }
+    }
+
+    /// A theme without any colors, for when `NO_COLOR` is set.
+    pub fn monochrome() -> Self {
+        let reversed = Style::default().add_modifier(Modifier::REVERSED);
+        Self {
+            tab_bar: reversed,
+            selected_tab: reversed.add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+            status_bar: reversed,
+            input: reversed,
+            inactive_input: Style::default().add_modifier(Modifier::UNDERLINED),
+            notice: reversed.add_modifier(Modifier::BOLD),
+            border: Style::default().add_modifier(Modifier::BOLD),
+            dim: Style::default().add_modifier(Modifier::ITALIC),
+            marker: reversed.add_modifier(Modifier::BOLD),
+            palette: vec![Color::Reset],
+        }
+    }
+
+    /// Creates the theme described by `config`, or a monochrome one if `no_color` is set.
+    pub fn new(config: &ThemeConfig, no_color: bool) -> Self {
+        if no_color {
+            return Self::monochrome();
+        }
+
+        let mut theme = Self::builtin(config.base);
+        for (style, colors) in [
+            (&mut theme.tab_bar, &config.tab_bar),
+            (&mut theme.selected_tab, &config.selected_tab),
+            (&mut theme.status_bar, &config.status_bar),
+            (&mut theme.input, &config.input),
+            (&mut theme.inactive_input, &config.inactive_input),
+            (&mut theme.notice, &config.notice),
+            (&mut theme.border, &config.border),
+            (&mut theme.marker, &config.marker),
+        ] {
+            if let Some(colors) = colors {
+                if let Some(fg) = colors.fg {
+                    *style = style.fg(fg.0);
+                }
+                if let Some(bg) = colors.bg {
+                    *style = style.bg(bg.0);
+                }
+            }
+        }
+        if let Some(palette) = config.palette.as_ref().filter(|p| !p.is_empty()) {
+            theme.palette = palette.iter().map(|color| color.0).collect();
+        }
+        theme
+    }
+
+    /// Returns the color of the n-th plot series or channel.
+    pub fn color(&self, n: usize) -> Color {
+        self.palette[n % self.palette.len()]
+    }
+}
+
+/// Returns true if colors are disabled with the `NO_COLOR` environment variable.
+///
+/// See <https://no-color.org/>.
+pub fn no_color() -> bool {
+    std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BuiltinTheme, ColorOverride, Theme, ThemeColor, ThemeConfig};
+    use std::convert::TryFrom;
+    use tui::style::Color;
+
+    fn color(s: &str) -> ThemeColor {
+        ThemeColor::try_from(s.to_owned()).unwrap()
+    }
+
+    #[test]
+    fn parse_colors() {
+        assert_eq!(color("LightBlue").0, Color::LightBlue);
+        assert_eq!(color("#ff8000").0, Color::Rgb(0xff, 0x80, 0x00));
+        assert_eq!(color("208").0, Color::Indexed(208));
+        assert!(ThemeColor::try_from("purple".to_owned()).is_err());
+        assert!(ThemeColor::try_from("#12345".to_owned()).is_err());
+    }
+
+    #[test]
+    fn overrides() {
+        let config = ThemeConfig {
+            base: BuiltinTheme::Light,
+            tab_bar: Some(ColorOverride {
+                fg: None,
+                bg: Some(color("Green")),
+            }),
+            selected_tab: None,
+            status_bar: None,
+            input: None,
+            inactive_input: None,
+            notice: None,
+            border: None,
+            marker: None,
+            palette: Some(vec![color("Red")]),
+        };
+
+        let theme = Theme::new(&config, false);
+        assert_eq!(theme.tab_bar.fg, Some(Color::White));
+        assert_eq!(theme.tab_bar.bg, Some(Color::Green));
+        assert_eq!(theme.color(3), Color::Red);
+
+        let theme = Theme::new(&config, true);
+        assert_eq!(theme.tab_bar.bg, None);
+        assert_eq!(theme.color(0), Color::Reset);
+    }
+}
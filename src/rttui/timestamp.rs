@@ -0,0 +1,217 @@
+use std::fmt::Write;
+
+use chrono::{
+    format::{Fixed, Item, Numeric, Pad, StrftimeItems},
+    DateTime, Duration, Local,
+};
+
+const DEFAULT_FORMAT: &str = "%H:%M:%S%.3f";
+
+/// What the host timestamp of a line shows.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum HostTimestamp {
+    /// The time of day the line was received.
+    #[default]
+    Absolute,
+    /// The time since the session started.
+    Relative,
+    /// The time since the previous line of the channel.
+    Delta,
+}
+
+/// Which timestamps are shown for defmt frames.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum DefmtTimestamp {
+    /// Only the time the frame was received by the host.
+    Host,
+    /// Only the timestamp of the firmware, set with `defmt::timestamp!`.
+    #[default]
+    Target,
+    /// The host time followed by the timestamp of the firmware.
+    Both,
+}
+
+/// How the lines of a channel are timestamped.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TimestampConfig {
+    #[serde(default)]
+    pub host: HostTimestamp,
+    /// A `strftime` like format string for the host timestamp, e.g. `%H:%M:%S%.3f`.
+    ///
+    /// Relative and delta timestamps are durations, which can only show hours, minutes, seconds
+    /// and fractions of a second. Their hours don't wrap after a day.
+    #[serde(default = "default_format")]
+    pub format: String,
+    #[serde(default)]
+    pub defmt: DefmtTimestamp,
+}
+
+fn default_format() -> String {
+    DEFAULT_FORMAT.to_owned()
+}
+
+impl Default for TimestampConfig {
+    fn default() -> Self {
+        Self {
+            host: HostTimestamp::default(),
+            format: default_format(),
+            defmt: DefmtTimestamp::default(),
+        }
+    }
+}
+
+/// Formats the host timestamps of a channel.
+#[derive(Debug)]
+pub struct Timestamper {
+    config: TimestampConfig,
+    start: DateTime<Local>,
+    /// When the previous line was received.
+    previous: Option<DateTime<Local>>,
+}
+
+impl Timestamper {
+    pub fn new(mut config: TimestampConfig, start: DateTime<Local>) -> Self {
+        if StrftimeItems::new(&config.format).any(|item| item == Item::Error) {
+            log::warn!(
+                "Invalid timestamp format '{}', using '{}' instead.",
+                config.format,
+                DEFAULT_FORMAT
+            );
+            config.format = default_format();
+        }
+
+        Self {
+            config,
+            start,
+            previous: None,
+        }
+    }
+
+    pub fn config(&self) -> &TimestampConfig {
+        &self.config
+    }
+
+    /// Formats the host timestamp of a line received at `time`.
+    pub fn host(&mut self, time: DateTime<Local>) -> String {
+        let since = |earlier: DateTime<Local>| {
+            let elapsed = (time - earlier).max(Duration::zero());
+            // A duration can't be formatted with a date, for example.
+            format_duration(elapsed, &self.config.format)
+                .or_else(|| format_duration(elapsed, DEFAULT_FORMAT))
+                .unwrap_or_default()
+        };
+
+        let output = match self.config.host {
+            HostTimestamp::Absolute => {
+                let mut output = String::new();
+                // A time zone can't be formatted for every time, for example.
+                match write!(output, "{}", time.format(&self.config.format)) {
+                    Ok(()) => output,
+                    Err(_) => time.format(DEFAULT_FORMAT).to_string(),
+                }
+            }
+            HostTimestamp::Relative => since(self.start),
+            HostTimestamp::Delta => since(self.previous.unwrap_or(time)),
+        };
+        self.previous = Some(time);
+        output
+    }
+}
+
+/// Formats a duration with the hour, minute, second and fraction specifiers of a `strftime` like
+/// format. The hours count the whole duration, so they don't wrap after a day.
+///
+/// Returns `None` if the format contains anything else.
+fn format_duration(duration: Duration, format: &str) -> Option<String> {
+    let seconds = duration.num_seconds();
+    let nanoseconds = (duration - Duration::seconds(seconds))
+        .num_nanoseconds()
+        .unwrap_or(0);
+
+    let mut output = String::new();
+    for item in StrftimeItems::new(format) {
+        let result = match item {
+            Item::Literal(text) | Item::Space(text) => write!(output, "{}", text),
+            Item::OwnedLiteral(text) | Item::OwnedSpace(text) => write!(output, "{}", text),
+            Item::Numeric(numeric, pad) => {
+                let value = match numeric {
+                    Numeric::Hour => seconds / 3600,
+                    Numeric::Minute => seconds / 60 % 60,
+                    Numeric::Second => seconds % 60,
+                    Numeric::Nanosecond => nanoseconds,
+                    _ => return None,
+                };
+                let width = if numeric == Numeric::Nanosecond { 9 } else { 2 };
+                match pad {
+                    Pad::None => write!(output, "{}", value),
+                    Pad::Zero => write!(output, "{:0width$}", value, width = width),
+                    Pad::Space => write!(output, "{:width$}", value, width = width),
+                }
+            }
+            Item::Fixed(Fixed::Nanosecond) => match nanoseconds {
+                0 => Ok(()),
+                _ if nanoseconds % 1_000_000 == 0 => {
+                    write!(output, ".{:03}", nanoseconds / 1_000_000)
+                }
+                _ if nanoseconds % 1_000 == 0 => write!(output, ".{:06}", nanoseconds / 1_000),
+                _ => write!(output, ".{:09}", nanoseconds),
+            },
+            Item::Fixed(Fixed::Nanosecond3) => write!(output, ".{:03}", nanoseconds / 1_000_000),
+            Item::Fixed(Fixed::Nanosecond6) => write!(output, ".{:06}", nanoseconds / 1_000),
+            Item::Fixed(Fixed::Nanosecond9) => write!(output, ".{:09}", nanoseconds),
+            _ => return None,
+        };
+        result.ok()?;
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{HostTimestamp, TimestampConfig, Timestamper};
+    use chrono::{Duration, Local, TimeZone};
+
+    #[test]
+    fn host_timestamps() {
+        let start = Local.with_ymd_and_hms(2022, 3, 4, 10, 0, 0).unwrap();
+        let config = |host, format: &str| TimestampConfig {
+            host,
+            format: format.to_owned(),
+            ..TimestampConfig::default()
+        };
+
+        let mut absolute = Timestamper::new(config(HostTimestamp::Absolute, "%H:%M"), start);
+        assert_eq!(absolute.host(start + Duration::minutes(5)), "10:05");
+
+        let mut relative = Timestamper::new(config(HostTimestamp::Relative, "%M:%S%.3f"), start);
+        assert_eq!(
+            relative.host(start + Duration::milliseconds(61_250)),
+            "01:01.250"
+        );
+
+        let mut delta = Timestamper::new(config(HostTimestamp::Delta, "%S%.3f"), start);
+        assert_eq!(delta.host(start + Duration::seconds(1)), "00.000");
+        assert_eq!(delta.host(start + Duration::milliseconds(1_500)), "00.500");
+
+        // Hours don't wrap after a day.
+        let mut relative = Timestamper::new(config(HostTimestamp::Relative, "%H:%M:%S"), start);
+        assert_eq!(
+            relative.host(start + Duration::hours(25) + Duration::seconds(3)),
+            "25:00:03"
+        );
+
+        // Dates can't be shown for relative times and invalid formats are replaced.
+        assert_eq!(
+            Timestamper::new(config(HostTimestamp::Relative, "%Y"), start)
+                .host(start + Duration::milliseconds(1_500)),
+            "00:00:01.500"
+        );
+        assert_eq!(
+            Timestamper::new(config(HostTimestamp::Absolute, "%Q"), start)
+                .config()
+                .format,
+            "%H:%M:%S%.3f"
+        );
+    }
+}
@@ -0,0 +1,540 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context, Result};
+use probe_rs::{Core, CoreStatus, CoreType, HaltReason, MemoryInterface};
+
+/// The instruction a semihosting request is made with, `BKPT 0xAB`.
+const BKPT_SEMIHOSTING: [u8; 2] = [0xab, 0xbe];
+
+const SYS_OPEN: u32 = 0x01;
+const SYS_CLOSE: u32 = 0x02;
+const SYS_WRITEC: u32 = 0x03;
+const SYS_WRITE0: u32 = 0x04;
+const SYS_WRITE: u32 = 0x05;
+const SYS_READ: u32 = 0x06;
+const SYS_SEEK: u32 = 0x0a;
+const SYS_FLEN: u32 = 0x0c;
+const SYS_EXIT: u32 = 0x18;
+const SYS_EXIT_EXTENDED: u32 = 0x20;
+
+/// The reason passed to `SYS_EXIT` when the application exits normally.
+const ADP_STOPPED_APPLICATION_EXIT: u32 = 0x20026;
+
+/// The result of a failed request.
+const FAILURE: u32 = u32::MAX;
+
+/// The name under which the target opens the console of the host.
+const CONSOLE: &[u8] = b":tt";
+
+/// The longest string accepted from the target, to not read the whole memory for a missing
+/// terminator.
+const MAX_STRING_LENGTH: usize = 4096;
+
+/// The most bytes written to the console by a single request. The rest is reported as not
+/// written, so the target writes it with the next request.
+const MAX_CONSOLE_WRITE: u32 = 65536;
+
+/// How many bytes are transferred between the target and a file at once.
+const CHUNK_SIZE: u32 = 4096;
+
+/// What happened on a semihosting request of the target.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The target wrote to the console.
+    Output(Vec<u8>),
+    /// The target exited with the given exit code.
+    Exit(i32),
+}
+
+/// The memory of the target, as far as semihosting needs it.
+pub trait TargetMemory {
+    fn read(&mut self, address: u32, data: &mut [u8]) -> Result<()>;
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<()>;
+
+    fn read_word(&mut self, address: u32) -> Result<u32> {
+        let mut word = [0; 4];
+        self.read(address, &mut word)?;
+        Ok(u32::from_le_bytes(word))
+    }
+}
+
+impl TargetMemory for Core<'_> {
+    fn read(&mut self, address: u32, data: &mut [u8]) -> Result<()> {
+        Ok(self.read_8(address.into(), data)?)
+    }
+
+    fn write(&mut self, address: u32, data: &[u8]) -> Result<()> {
+        Ok(self.write_8(address.into(), data)?)
+    }
+}
+
+#[derive(Debug)]
+enum Handle {
+    Console,
+    File(File),
+}
+
+/// Services the semihosting requests of a Cortex-M target.
+///
+/// Files are only opened inside the host directory, without it the target can only use the
+/// console.
+#[derive(Debug)]
+pub struct Semihosting {
+    host_directory: Option<PathBuf>,
+    handles: HashMap<u32, Handle>,
+    next_handle: u32,
+}
+
+impl Semihosting {
+    pub fn new(host_directory: Option<PathBuf>) -> Self {
+        Self {
+            host_directory,
+            handles: HashMap::new(),
+            next_handle: 1,
+        }
+    }
+
+    /// Whether requests of a core of the given type can be serviced. Only the Thumb `BKPT 0xAB`
+    /// of M-profile cores is recognized, not the `HLT`/`SVC` of A and R-profile cores or the
+    /// `EBREAK` sequence of RISC-V.
+    pub fn supports(core_type: CoreType) -> bool {
+        matches!(
+            core_type,
+            CoreType::Armv6m | CoreType::Armv7m | CoreType::Armv7em | CoreType::Armv8m
+        )
+    }
+
+    /// Services the request the core is halted at, if any, and resumes the core unless the
+    /// target exited.
+    pub fn poll(&mut self, core: &mut Core) -> Result<Option<Event>> {
+        if core.status()? != CoreStatus::Halted(HaltReason::Breakpoint) {
+            return Ok(None);
+        }
+        let registers = core.registers();
+        let pc = core.read_core_reg(registers.program_counter())?;
+        let mut instruction = [0; 2];
+        TargetMemory::read(core, pc, &mut instruction)?;
+        if instruction != BKPT_SEMIHOSTING {
+            // A breakpoint of a debugger.
+            return Ok(None);
+        }
+
+        let operation = core.read_core_reg(registers.argument_register(0))?;
+        let parameter = core.read_core_reg(registers.argument_register(1))?;
+        let (result, event) = self.service(core, operation, parameter)?;
+        if let Some(Event::Exit(_)) = event {
+            return Ok(event);
+        }
+
+        core.write_core_reg(registers.argument_register(0).into(), result)?;
+        core.write_core_reg(registers.program_counter().into(), pc + 2)?;
+        core.run()?;
+        Ok(event)
+    }
+
+    /// Services the request `operation` with the parameter, usually the address of a parameter
+    /// block, and returns the result for the target.
+    fn service(
+        &mut self,
+        memory: &mut dyn TargetMemory,
+        operation: u32,
+        parameter: u32,
+    ) -> Result<(u32, Option<Event>)> {
+        match operation {
+            SYS_OPEN => {
+                let name = memory.read_word(parameter)?;
+                let mode = memory.read_word(offset(parameter, 4)?)?;
+                let length = memory.read_word(offset(parameter, 8)?)?;
+                if length as usize > MAX_STRING_LENGTH {
+                    log::warn!("Semihosting: The target tried to open a file with a too long name");
+                    return Ok((FAILURE, None));
+                }
+                let mut name_bytes = vec![0; length as usize];
+                memory.read(name, &mut name_bytes)?;
+
+                let handle = if name_bytes == CONSOLE {
+                    Handle::Console
+                } else {
+                    let name = String::from_utf8_lossy(&name_bytes);
+                    match self.open(&name, mode) {
+                        Ok(file) => Handle::File(file),
+                        Err(e) => {
+                            log::warn!("Semihosting: {:#}", e);
+                            return Ok((FAILURE, None));
+                        }
+                    }
+                };
+                let number = self.next_handle;
+                self.next_handle += 1;
+                self.handles.insert(number, handle);
+                Ok((number, None))
+            }
+            SYS_CLOSE => {
+                let handle = memory.read_word(parameter)?;
+                match self.handles.remove(&handle) {
+                    Some(_) => Ok((0, None)),
+                    None => Ok((FAILURE, None)),
+                }
+            }
+            SYS_WRITEC => {
+                let mut character = [0];
+                memory.read(parameter, &mut character)?;
+                Ok((0, Some(Event::Output(character.to_vec()))))
+            }
+            SYS_WRITE0 => {
+                let text = read_string(memory, parameter)?;
+                Ok((0, Some(Event::Output(text))))
+            }
+            SYS_WRITE => {
+                let handle = memory.read_word(parameter)?;
+                let address = memory.read_word(offset(parameter, 4)?)?;
+                let length = memory.read_word(offset(parameter, 8)?)?;
+                offset(address, length)?;
+
+                // The result is the number of bytes which were not written.
+                match self.handles.get_mut(&handle) {
+                    Some(Handle::Console) => {
+                        let mut data = vec![0; length.min(MAX_CONSOLE_WRITE) as usize];
+                        memory.read(address, &mut data)?;
+                        Ok((length - data.len() as u32, Some(Event::Output(data))))
+                    }
+                    Some(Handle::File(file)) => {
+                        let mut written = 0;
+                        while written < length {
+                            let mut chunk = vec![0; (length - written).min(CHUNK_SIZE) as usize];
+                            memory.read(address + written, &mut chunk)?;
+                            if file.write_all(&chunk).is_err() {
+                                break;
+                            }
+                            written += chunk.len() as u32;
+                        }
+                        Ok((length - written, None))
+                    }
+                    None => Ok((length, None)),
+                }
+            }
+            SYS_READ => {
+                let handle = memory.read_word(parameter)?;
+                let address = memory.read_word(offset(parameter, 4)?)?;
+                let length = memory.read_word(offset(parameter, 8)?)?;
+                offset(address, length)?;
+
+                // The result is the number of bytes which were not read, reading from the
+                // console always hits its end.
+                match self.handles.get_mut(&handle) {
+                    Some(Handle::File(file)) => {
+                        let mut read = 0;
+                        while read < length {
+                            let mut chunk = vec![0; (length - read).min(CHUNK_SIZE) as usize];
+                            let count = match file.read(&mut chunk) {
+                                Ok(0) => break,
+                                Ok(count) => count,
+                                Err(_) => return Ok((FAILURE, None)),
+                            };
+                            memory.write(address + read, &chunk[..count])?;
+                            read += count as u32;
+                        }
+                        Ok((length - read, None))
+                    }
+                    Some(Handle::Console) => Ok((length, None)),
+                    None => Ok((FAILURE, None)),
+                }
+            }
+            SYS_SEEK => {
+                let handle = memory.read_word(parameter)?;
+                let position = memory.read_word(offset(parameter, 4)?)?;
+                match self.handles.get_mut(&handle) {
+                    Some(Handle::File(file)) => match file.seek(SeekFrom::Start(position.into())) {
+                        Ok(_) => Ok((0, None)),
+                        Err(_) => Ok((FAILURE, None)),
+                    },
+                    _ => Ok((FAILURE, None)),
+                }
+            }
+            SYS_FLEN => {
+                let handle = memory.read_word(parameter)?;
+                match self.handles.get(&handle) {
+                    Some(Handle::File(file)) => match file.metadata() {
+                        Ok(metadata) => Ok((metadata.len() as u32, None)),
+                        Err(_) => Ok((FAILURE, None)),
+                    },
+                    _ => Ok((FAILURE, None)),
+                }
+            }
+            // On 32 bit targets the parameter of `SYS_EXIT` is the reason itself.
+            SYS_EXIT => {
+                let code = if parameter == ADP_STOPPED_APPLICATION_EXIT {
+                    0
+                } else {
+                    1
+                };
+                Ok((0, Some(Event::Exit(code))))
+            }
+            SYS_EXIT_EXTENDED => {
+                let reason = memory.read_word(parameter)?;
+                let subcode = memory.read_word(offset(parameter, 4)?)?;
+                let code = if reason == ADP_STOPPED_APPLICATION_EXIT {
+                    subcode as i32
+                } else {
+                    1
+                };
+                Ok((0, Some(Event::Exit(code))))
+            }
+            _ => {
+                log::warn!("Semihosting operation {:#04x} is not supported.", operation);
+                Ok((FAILURE, None))
+            }
+        }
+    }
+
+    /// Opens `name` inside the host directory, in the `fopen` mode with the number `mode`.
+    fn open(&self, name: &str, mode: u32) -> Result<File> {
+        let directory = self.host_directory.as_ref().with_context(|| {
+            format!(
+                "The target tried to open {}, but no host directory is configured",
+                name
+            )
+        })?;
+        let path = sandboxed_path(directory, name)?;
+
+        // The modes are `r`, `w` and `a`, each plain, binary, with `+` and binary with `+`.
+        let update = mode & 2 != 0;
+        let mut options = OpenOptions::new();
+        match mode / 4 {
+            0 => options.read(true).write(update),
+            1 => options.write(true).read(update).create(true).truncate(true),
+            _ => options.append(true).read(update).create(true),
+        };
+        options
+            .open(&path)
+            .with_context(|| format!("Could not open {}", path.display()))
+    }
+}
+
+/// Resolves `name` inside `directory`, refusing names which lead out of it, also through
+/// symbolic links.
+fn sandboxed_path(directory: &Path, name: &str) -> Result<PathBuf> {
+    let outside = || {
+        anyhow!(
+            "The target tried to open {}, which is outside of the host directory",
+            name
+        )
+    };
+    if !Path::new(name)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(outside());
+    }
+
+    let path = directory.join(name);
+    let directory = directory
+        .canonicalize()
+        .with_context(|| format!("Could not open the host directory {}", directory.display()))?;
+    let resolved = match path.canonicalize() {
+        Ok(resolved) => resolved,
+        // A link to a missing file would be created outside of the directory.
+        Err(_) if path.symlink_metadata().is_ok() => return Err(outside()),
+        // A file which does not exist yet is resolved through its parent directory.
+        Err(_) => {
+            let file_name = path.file_name().ok_or_else(outside)?;
+            let parent = path.parent().ok_or_else(outside)?;
+            parent
+                .canonicalize()
+                .with_context(|| format!("Could not open {}", path.display()))?
+                .join(file_name)
+        }
+    };
+    if !resolved.starts_with(&directory) {
+        return Err(outside());
+    }
+    Ok(resolved)
+}
+
+/// Returns `address` advanced by `offset`, or an error if that leaves the address space.
+fn offset(address: u32, offset: u32) -> Result<u32> {
+    address.checked_add(offset).ok_or_else(|| {
+        anyhow!(
+            "The target passed {:#010x} bytes at {:#010x}, which exceeds the address space",
+            offset,
+            address
+        )
+    })
+}
+
+/// Reads the zero terminated string at `address`.
+fn read_string(memory: &mut dyn TargetMemory, address: u32) -> Result<Vec<u8>> {
+    let mut text = Vec::new();
+    let mut chunk = [0; 32];
+    while text.len() < MAX_STRING_LENGTH {
+        memory.read(offset(address, text.len() as u32)?, &mut chunk)?;
+        match chunk.iter().position(|&byte| byte == 0) {
+            Some(end) => {
+                text.extend_from_slice(&chunk[..end]);
+                return Ok(text);
+            }
+            None => text.extend_from_slice(&chunk),
+        }
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        Event, Semihosting, TargetMemory, ADP_STOPPED_APPLICATION_EXIT, FAILURE, SYS_EXIT,
+        SYS_EXIT_EXTENDED, SYS_OPEN, SYS_READ, SYS_WRITE, SYS_WRITE0,
+    };
+    use anyhow::Result;
+    use probe_rs::CoreType;
+
+    /// The memory of a target, starting at address 0. Reads past its end return zeros.
+    struct Memory(Vec<u8>);
+
+    impl Memory {
+        fn put(&mut self, address: u32, data: &[u8]) {
+            self.0[address as usize..address as usize + data.len()].copy_from_slice(data);
+        }
+
+        fn put_words(&mut self, address: u32, words: &[u32]) {
+            for (i, word) in words.iter().enumerate() {
+                self.put(address + 4 * i as u32, &word.to_le_bytes());
+            }
+        }
+    }
+
+    impl TargetMemory for Memory {
+        fn read(&mut self, address: u32, data: &mut [u8]) -> Result<()> {
+            for (i, byte) in data.iter_mut().enumerate() {
+                *byte = self.0.get(address as usize + i).copied().unwrap_or(0);
+            }
+            Ok(())
+        }
+
+        fn write(&mut self, address: u32, data: &[u8]) -> Result<()> {
+            self.put(address, data);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn console_output_and_exit() {
+        let mut memory = Memory(vec![0; 256]);
+        let mut semihosting = Semihosting::new(None);
+
+        memory.put(0x80, b"Hello\n\0");
+        let (result, event) = semihosting.service(&mut memory, SYS_WRITE0, 0x80).unwrap();
+        assert_eq!(result, 0);
+        assert_eq!(event, Some(Event::Output(b"Hello\n".to_vec())));
+
+        // Open the console and write to it.
+        memory.put(0x40, b":tt");
+        memory.put_words(0x00, &[0x40, 4, 3]);
+        let (handle, _) = semihosting.service(&mut memory, SYS_OPEN, 0x00).unwrap();
+        memory.put_words(0x10, &[handle, 0x80, 5]);
+        let (result, event) = semihosting.service(&mut memory, SYS_WRITE, 0x10).unwrap();
+        assert_eq!(result, 0);
+        assert_eq!(event, Some(Event::Output(b"Hello".to_vec())));
+
+        let (_, event) = semihosting
+            .service(&mut memory, SYS_EXIT, ADP_STOPPED_APPLICATION_EXIT)
+            .unwrap();
+        assert_eq!(event, Some(Event::Exit(0)));
+        let (_, event) = semihosting.service(&mut memory, SYS_EXIT, 0x20023).unwrap();
+        assert_eq!(event, Some(Event::Exit(1)));
+        memory.put_words(0x20, &[ADP_STOPPED_APPLICATION_EXIT, 3]);
+        let (_, event) = semihosting
+            .service(&mut memory, SYS_EXIT_EXTENDED, 0x20)
+            .unwrap();
+        assert_eq!(event, Some(Event::Exit(3)));
+    }
+
+    #[test]
+    fn supported_cores() {
+        assert!(Semihosting::supports(CoreType::Armv6m));
+        assert!(Semihosting::supports(CoreType::Armv8m));
+        assert!(!Semihosting::supports(CoreType::Armv7a));
+        assert!(!Semihosting::supports(CoreType::Riscv));
+    }
+
+    #[test]
+    fn files_in_host_directory() {
+        let directory =
+            std::env::temp_dir().join(format!("cargo-embed-semihosting-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let mut memory = Memory(vec![0; 256]);
+        let mut semihosting = Semihosting::new(Some(directory.clone()));
+
+        // Write a file.
+        memory.put(0x40, b"out.txt");
+        memory.put_words(0x00, &[0x40, 4, 7]);
+        let (handle, _) = semihosting.service(&mut memory, SYS_OPEN, 0x00).unwrap();
+        assert_ne!(handle, FAILURE);
+        memory.put(0x80, b"data");
+        memory.put_words(0x10, &[handle, 0x80, 4]);
+        let (result, _) = semihosting.service(&mut memory, SYS_WRITE, 0x10).unwrap();
+        assert_eq!(result, 0);
+        assert_eq!(std::fs::read(directory.join("out.txt")).unwrap(), b"data");
+
+        // Read it back, asking for more than there is.
+        memory.put_words(0x00, &[0x40, 0, 7]);
+        let (handle, _) = semihosting.service(&mut memory, SYS_OPEN, 0x00).unwrap();
+        memory.put_words(0x10, &[handle, 0xc0, 8]);
+        let (result, _) = semihosting.service(&mut memory, SYS_READ, 0x10).unwrap();
+        assert_eq!(result, 4);
+        assert_eq!(&memory.0[0xc0..0xc4], b"data");
+
+        // Files outside of the host directory can't be opened.
+        memory.put(0x40, b"../out.txt");
+        memory.put_words(0x00, &[0x40, 0, 10]);
+        let (handle, _) = semihosting.service(&mut memory, SYS_OPEN, 0x00).unwrap();
+        assert_eq!(handle, FAILURE);
+
+        // Neither through a link.
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(std::env::temp_dir(), directory.join("link")).unwrap();
+            memory.put(0x40, b"link/out.txt");
+            memory.put_words(0x00, &[0x40, 4, 12]);
+            let (handle, _) = semihosting.service(&mut memory, SYS_OPEN, 0x00).unwrap();
+            assert_eq!(handle, FAILURE);
+            assert!(!std::env::temp_dir().join("out.txt").exists());
+        }
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn untrusted_lengths() {
+        let mut memory = Memory(vec![0; 256]);
+        let mut semihosting = Semihosting::new(None);
+
+        // A parameter block at the end of the address space.
+        assert!(semihosting
+            .service(&mut memory, SYS_WRITE, 0xffff_fffc)
+            .is_err());
+
+        // A buffer which wraps around the address space.
+        memory.put_words(0x00, &[1, 0x80, u32::MAX]);
+        assert!(semihosting.service(&mut memory, SYS_WRITE, 0x00).is_err());
+
+        // Overlong names are not read.
+        memory.put_words(0x00, &[0x40, 0, u32::MAX]);
+        let (handle, _) = semihosting.service(&mut memory, SYS_OPEN, 0x00).unwrap();
+        assert_eq!(handle, FAILURE);
+
+        // Large console writes are bounded, the rest is reported as not written.
+        memory.put(0x40, b":tt");
+        memory.put_words(0x00, &[0x40, 4, 3]);
+        let (handle, _) = semihosting.service(&mut memory, SYS_OPEN, 0x00).unwrap();
+        memory.put_words(0x00, &[handle, 0x10, 0x18000]);
+        let (result, event) = semihosting.service(&mut memory, SYS_WRITE, 0x00).unwrap();
+        assert_eq!(result, 0x8000);
+        assert!(matches!(event, Some(Event::Output(data)) if data.len() == 0x10000));
+    }
+}
@@ -0,0 +1,417 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use probe_rs::{architecture::arm::SwoConfig, MemoryInterface, Session};
+
+use crate::config;
+
+const ITM_TER: u64 = 0xe000_0e00;
+const ITM_TCR: u64 = 0xe000_0e80;
+const ITM_LAR: u64 = 0xe000_0fb0;
+const DWT_CTRL: u64 = 0xe000_1000;
+const DEMCR: u64 = 0xe000_edfc;
+
+/// Unlocks the registers of the ITM.
+const LAR_KEY: u32 = 0xc5ac_ce55;
+const DEMCR_TRCENA: u32 = 1 << 24;
+
+const TCR_ITMENA: u32 = 1 << 0;
+const TCR_SYNCENA: u32 = 1 << 2;
+const TCR_DWTENA: u32 = 1 << 3;
+/// The ID of the ITM on the trace bus, which has to be set for the TPIU to forward packets.
+const TCR_TRACE_BUS_ID: u32 = 1 << 16;
+
+const DWT_CYCCNTENA: u32 = 1 << 0;
+/// Samples the program counter every 16 taps of the cycle counter.
+const DWT_POSTPRESET: u32 = 0xf << 1;
+/// Taps the cycle counter at bit 10 instead of bit 6.
+const DWT_CYCTAP: u32 = 1 << 9;
+const DWT_PCSAMPLENA: u32 = 1 << 12;
+const DWT_EXCTRCENA: u32 = 1 << 16;
+/// The bits of `DWT_CTRL` which configure sampling and tracing.
+const DWT_TRACE_MASK: u32 =
+    DWT_CYCCNTENA | DWT_POSTPRESET | DWT_CYCTAP | DWT_PCSAMPLENA | DWT_EXCTRCENA;
+
+/// The longest line held back, longer ones are split.
+const MAX_LINE_LENGTH: usize = 1024;
+/// How long an incomplete line is held back before it is shown anyway.
+const LINE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Where the data captured over SWO comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    /// An ITM stimulus port, written to by the firmware.
+    Port(u8),
+    /// The packets of the DWT, decoded into lines of text.
+    Dwt,
+}
+
+/// A packet of the ITM protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Packet {
+    Instrumentation {
+        port: u8,
+        data: Vec<u8>,
+    },
+    ExceptionTrace {
+        number: u16,
+        function: u8,
+    },
+    /// A sample of the program counter, or `None` if the core was sleeping.
+    PcSample(Option<u32>),
+    Overflow,
+}
+
+/// Splits the byte stream of the ITM into packets.
+///
+/// Packets may be split across reads, the incomplete end of a read is kept for the next one.
+#[derive(Debug, Default)]
+struct ItmDecoder {
+    pending: Vec<u8>,
+}
+
+impl ItmDecoder {
+    fn feed(&mut self, data: &[u8]) -> Vec<Packet> {
+        self.pending.extend_from_slice(data);
+
+        let mut packets = Vec::new();
+        let mut i = 0;
+        while i < self.pending.len() {
+            let header = self.pending[i];
+            let length = match header {
+                // Part of a synchronization packet, which ends with 0x80.
+                0x00 | 0x80 => 0,
+                0x70 => {
+                    packets.push(Packet::Overflow);
+                    0
+                }
+                // Timestamps and extension packets, which are continued while bit 7 is set.
+                _ if header & 0x03 == 0 => {
+                    if header & 0x80 == 0 {
+                        0
+                    } else {
+                        match self.pending[i + 1..].iter().position(|b| b & 0x80 == 0) {
+                            Some(end) => end + 1,
+                            None => break,
+                        }
+                    }
+                }
+                _ => {
+                    let length = match header & 0x03 {
+                        1 => 1,
+                        2 => 2,
+                        _ => 4,
+                    };
+                    if i + length >= self.pending.len() {
+                        break;
+                    }
+                    let payload = &self.pending[i + 1..i + 1 + length];
+                    if let Some(packet) = source_packet(header, payload) {
+                        packets.push(packet);
+                    }
+                    length
+                }
+            };
+            i += 1 + length;
+        }
+        self.pending.drain(..i);
+        packets
+    }
+}
+
+/// Decodes a packet of the software (ITM) or hardware (DWT) source.
+fn source_packet(header: u8, payload: &[u8]) -> Option<Packet> {
+    let id = header >> 3;
+    if header & 0x04 == 0 {
+        return Some(Packet::Instrumentation {
+            port: id,
+            data: payload.to_vec(),
+        });
+    }
+    match (id, payload) {
+        (1, [low, high]) => Some(Packet::ExceptionTrace {
+            number: u16::from(*low) | u16::from(*high & 0x01) << 8,
+            function: (*high >> 4) & 0x03,
+        }),
+        (2, [_]) => Some(Packet::PcSample(None)),
+        (2, [a, b, c, d]) => Some(Packet::PcSample(Some(u32::from_le_bytes([*a, *b, *c, *d])))),
+        // Event counters and data trace are not configured.
+        _ => None,
+    }
+}
+
+fn exception_name(number: u16) -> String {
+    match number {
+        0 => "Thread".to_owned(),
+        1 => "Reset".to_owned(),
+        2 => "NMI".to_owned(),
+        3 => "HardFault".to_owned(),
+        4 => "MemManage".to_owned(),
+        5 => "BusFault".to_owned(),
+        6 => "UsageFault".to_owned(),
+        11 => "SVCall".to_owned(),
+        12 => "DebugMonitor".to_owned(),
+        14 => "PendSV".to_owned(),
+        15 => "SysTick".to_owned(),
+        n if n >= 16 => format!("IRQ {}", n - 16),
+        n => format!("Exception {}", n),
+    }
+}
+
+/// Captures the output of a target over SWO.
+#[derive(Debug)]
+pub struct Swo {
+    ports: Vec<u8>,
+    decoder: ItmDecoder,
+}
+
+impl Swo {
+    /// Configures the trace output of the first core as given in `config`.
+    pub fn setup(session: &mut Session, config: &config::Swo) -> Result<Self> {
+        let clock = config
+            .clock
+            .ok_or_else(|| anyhow!("The trace clock has to be set with `swo.clock` to use SWO"))?;
+        session.setup_swv(0, &SwoConfig::new(clock).set_baud(config.baud_rate))?;
+
+        let dwt = config.pc_sampling || config.exception_trace;
+        let mut core = session.core(0)?;
+        let demcr = core.read_word_32(DEMCR)?;
+        core.write_word_32(DEMCR, demcr | DEMCR_TRCENA)?;
+
+        core.write_word_32(ITM_LAR, LAR_KEY)?;
+        let mut tcr = TCR_ITMENA | TCR_SYNCENA | TCR_TRACE_BUS_ID;
+        if dwt {
+            tcr |= TCR_DWTENA;
+        }
+        core.write_word_32(ITM_TCR, tcr)?;
+        let ports = config
+            .ports
+            .iter()
+            .filter(|&&port| port < 32)
+            .fold(0, |mask, port| mask | 1 << port);
+        core.write_word_32(ITM_TER, ports)?;
+
+        let mut ctrl = core.read_word_32(DWT_CTRL)? & !DWT_TRACE_MASK;
+        if config.pc_sampling {
+            ctrl |= DWT_CYCCNTENA | DWT_POSTPRESET | DWT_CYCTAP | DWT_PCSAMPLENA;
+        }
+        if config.exception_trace {
+            ctrl |= DWT_EXCTRCENA;
+        }
+        core.write_word_32(DWT_CTRL, ctrl)?;
+
+        Ok(Self {
+            ports: config.ports.clone(),
+            decoder: ItmDecoder::default(),
+        })
+    }
+
+    /// The streams captured, in the order of the configured ports, followed by the DWT.
+    pub fn streams(config: &config::Swo) -> Vec<Stream> {
+        let mut streams = config
+            .ports
+            .iter()
+            .copied()
+            .map(Stream::Port)
+            .collect::<Vec<_>>();
+        if config.pc_sampling || config.exception_trace {
+            streams.push(Stream::Dwt);
+        }
+        streams
+    }
+
+    /// Returns the data received since the last call, consecutive data of a stream joined.
+    pub fn poll(&mut self, session: &mut Session) -> Result<Vec<(Stream, Vec<u8>)>> {
+        let data = session.read_swo()?;
+        Ok(self.decode(&data))
+    }
+
+    fn decode(&mut self, data: &[u8]) -> Vec<(Stream, Vec<u8>)> {
+        let mut output: Vec<(Stream, Vec<u8>)> = Vec::new();
+        for packet in self.decoder.feed(data) {
+            let (stream, data) = match packet {
+                Packet::Instrumentation { port, data } if self.ports.contains(&port) => {
+                    (Stream::Port(port), data)
+                }
+                Packet::Instrumentation { .. } => continue,
+                Packet::ExceptionTrace { number, function } => {
+                    let action = match function {
+                        1 => "entered",
+                        2 => "exited",
+                        _ => "returned to",
+                    };
+                    let line = format!("{} {}\n", exception_name(number), action);
+                    (Stream::Dwt, line.into_bytes())
+                }
+                Packet::PcSample(Some(pc)) => {
+                    (Stream::Dwt, format!("PC {:#010x}\n", pc).into_bytes())
+                }
+                Packet::PcSample(None) => (Stream::Dwt, b"PC sleeping\n".to_vec()),
+                Packet::Overflow => {
+                    log::warn!("The SWO output overflowed, some trace data was lost.");
+                    continue;
+                }
+            };
+            match output.last_mut() {
+                Some((last, last_data)) if *last == stream => last_data.extend(data),
+                _ => output.push((stream, data)),
+            }
+        }
+        output
+    }
+}
+
+impl Stream {
+    /// The name of the tab showing the stream.
+    pub fn name(self) -> String {
+        match self {
+            Stream::Port(port) => format!("ITM {}", port),
+            Stream::Dwt => "DWT".to_owned(),
+        }
+    }
+}
+
+/// Joins the streams into a single output, each line prefixed with the name of its stream.
+///
+/// Incomplete lines are held back until they are complete, so lines of different streams are
+/// not mixed. A stream which never ends its lines still shows up, as lines are split after
+/// [MAX_LINE_LENGTH] bytes and by [PrefixedLines::flush_stale].
+#[derive(Debug, Default)]
+pub struct PrefixedLines {
+    /// The incomplete line of each stream, with the time it was started.
+    pending: Vec<(Stream, Vec<u8>, Instant)>,
+}
+
+impl PrefixedLines {
+    /// Adds the data of a stream and returns the lines it completed.
+    pub fn push(&mut self, stream: Stream, data: &[u8]) -> Vec<u8> {
+        let now = Instant::now();
+        let index = match self.pending.iter().position(|(s, _, _)| *s == stream) {
+            Some(index) => index,
+            None => {
+                self.pending.push((stream, Vec::new(), now));
+                self.pending.len() - 1
+            }
+        };
+        let (_, pending, started) = &mut self.pending[index];
+        if pending.is_empty() {
+            *started = now;
+        }
+        pending.extend_from_slice(data);
+
+        let mut output = Vec::new();
+        while let Some(end) = pending.iter().position(|&b| b == b'\n') {
+            output.extend_from_slice(format!("[{}] ", stream.name()).as_bytes());
+            output.extend(pending.drain(..=end));
+            *started = now;
+        }
+        while pending.len() >= MAX_LINE_LENGTH {
+            output.extend_from_slice(format!("[{}] ", stream.name()).as_bytes());
+            output.extend(pending.drain(..MAX_LINE_LENGTH));
+            output.push(b'\n');
+        }
+        output
+    }
+
+    /// Returns the incomplete lines which were started more than [LINE_TIMEOUT] before `now`.
+    pub fn flush_stale(&mut self, now: Instant) -> Vec<u8> {
+        let mut output = Vec::new();
+        for (stream, pending, started) in &mut self.pending {
+            if !pending.is_empty() && now.saturating_duration_since(*started) >= LINE_TIMEOUT {
+                output.extend_from_slice(format!("[{}] ", stream.name()).as_bytes());
+                output.append(pending);
+                output.push(b'\n');
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use super::{ItmDecoder, Packet, PrefixedLines, Stream, Swo, LINE_TIMEOUT, MAX_LINE_LENGTH};
+
+    #[test]
+    fn itm_packets() {
+        let mut decoder = ItmDecoder::default();
+        // A synchronization packet, then "Hi" on port 0 and a word on port 1, split in the middle.
+        let packets = decoder.feed(&[
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x01, b'H', 0x01, b'i', 0x0b, 1, 2,
+        ]);
+        assert_eq!(
+            packets,
+            [
+                Packet::Instrumentation {
+                    port: 0,
+                    data: b"H".to_vec()
+                },
+                Packet::Instrumentation {
+                    port: 0,
+                    data: b"i".to_vec()
+                },
+            ]
+        );
+        // A local timestamp with a continuation byte, then an exception trace packet.
+        let packets = decoder.feed(&[3, 4, 0xc0, 0x05, 0x0e, 0x0f, 0x10]);
+        assert_eq!(
+            packets,
+            [
+                Packet::Instrumentation {
+                    port: 1,
+                    data: vec![1, 2, 3, 4]
+                },
+                Packet::ExceptionTrace {
+                    number: 15,
+                    function: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn streams() {
+        let mut swo = Swo {
+            ports: vec![0],
+            decoder: ItmDecoder::default(),
+        };
+        let output = swo.decode(&[
+            0x01, b'a', 0x01, b'b', 0x09, b'x', 0x17, 0x00, 0x01, 0x00, 0x08, 0x15, 0x00,
+        ]);
+        assert_eq!(
+            output,
+            [
+                (Stream::Port(0), b"ab".to_vec()),
+                (Stream::Dwt, b"PC 0x08000100\nPC sleeping\n".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn prefixed_lines() {
+        let mut lines = PrefixedLines::default();
+        assert_eq!(lines.push(Stream::Port(0), b"Hel"), b"");
+        assert_eq!(
+            lines.push(Stream::Dwt, b"PC sleeping\n"),
+            b"[DWT] PC sleeping\n"
+        );
+        assert_eq!(
+            lines.push(Stream::Port(0), b"lo\nA\nB"),
+            b"[ITM 0] Hello\n[ITM 0] A\n".to_vec()
+        );
+
+        // Lines without an end are shown after a while, or when they get too long.
+        assert_eq!(lines.flush_stale(Instant::now()), b"");
+        assert_eq!(
+            lines.flush_stale(Instant::now() + LINE_TIMEOUT),
+            b"[ITM 0] B\n".to_vec()
+        );
+        let output = lines.push(Stream::Port(1), &[b'x'; MAX_LINE_LENGTH + 1]);
+        assert_eq!(output.len(), "[ITM 1] \n".len() + MAX_LINE_LENGTH);
+        assert_eq!(
+            lines.flush_stale(Instant::now() + LINE_TIMEOUT),
+            b"[ITM 1] x\n".to_vec()
+        );
+    }
+}
@@ -0,0 +1,331 @@
+use std::{
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use gimli::{EndianSlice, FileEntry, LineProgramHeader, LittleEndian, Unit};
+use goblin::elf::{sym::STT_FUNC, Elf};
+
+type Reader<'a> = EndianSlice<'a, LittleEndian>;
+
+/// A function of the firmware.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Function {
+    address: u32,
+    size: u32,
+    name: String,
+}
+
+/// The start of a range of instructions generated for a line of source code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LineRow {
+    address: u32,
+    /// The index of the file in [Symbols::files], or `None` at the end of a sequence of
+    /// instructions.
+    file: Option<usize>,
+    line: u32,
+}
+
+/// The symbols of the firmware, to tell which function an address belongs to.
+#[derive(Debug, Default)]
+pub struct Symbols {
+    /// Sorted by address.
+    functions: Vec<Function>,
+    /// Sorted by address.
+    lines: Vec<LineRow>,
+    /// The source files of [Symbols::lines], relative to the current directory if inside it.
+    files: Vec<PathBuf>,
+    /// The `.debug_frame` section, describing how to unwind each function.
+    debug_frame: Option<Vec<u8>>,
+}
+
+impl Symbols {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let elf = Elf::parse(data)?;
+
+        let mut functions = elf
+            .syms
+            .iter()
+            .filter(|sym| sym.st_type() == STT_FUNC && sym.st_value != 0)
+            .filter_map(|sym| {
+                let name = elf.strtab.get_at(sym.st_name)?;
+                // The lowest bit of Thumb functions is set.
+                let address = sym.st_value as u32 & !1;
+                // Functions without a size, e.g. from assembly, end with their section at the
+                // latest.
+                let size = match elf.section_headers.get(sym.st_shndx) {
+                    Some(section) if sym.st_size == 0 => {
+                        (section.sh_addr + section.sh_size).saturating_sub(u64::from(address))
+                    }
+                    _ => sym.st_size,
+                };
+                Some(Function {
+                    address,
+                    size: size as u32,
+                    name: format!("{:#}", rustc_demangle::demangle(name)),
+                })
+            })
+            .collect::<Vec<_>>();
+        functions.sort_by_key(|function| function.address);
+        functions.dedup_by_key(|function| function.address);
+
+        // Functions are still symbolized when the line info is unreadable.
+        let (lines, files) = parse_lines(&elf, data).unwrap_or_else(|e| {
+            log::warn!(
+                "Source lines are not shown, reading the line info failed: {}",
+                e
+            );
+            (Vec::new(), Vec::new())
+        });
+        let debug_frame = section(&elf, data, ".debug_frame").map(<[u8]>::to_vec);
+
+        Ok(Self {
+            functions,
+            lines,
+            files,
+            debug_frame,
+        })
+    }
+
+    /// Returns the name of the function containing `address` and the offset into it.
+    pub fn function(&self, address: u32) -> Option<(&str, u32)> {
+        let index = self
+            .functions
+            .partition_point(|function| function.address <= address)
+            .checked_sub(1)?;
+        let function = &self.functions[index];
+        let offset = address - function.address;
+        // Functions without a size extend to the next function, the last one is not matched
+        // as it would claim every higher address.
+        let last = index + 1 == self.functions.len();
+        if function.size == 0 && last || function.size != 0 && offset >= function.size {
+            return None;
+        }
+        Some((&function.name, offset))
+    }
+
+    /// Returns the source file and line of the instruction at `address`.
+    pub fn location(&self, address: u32) -> Option<(&Path, u32)> {
+        let index = self
+            .lines
+            .partition_point(|row| row.address <= address)
+            .checked_sub(1)?;
+        let row = self.lines[index];
+        Some((&self.files[row.file?], row.line))
+    }
+
+    /// Returns the address of the function called `name`.
+    pub fn address_of(&self, name: &str) -> Option<u32> {
+        self.functions
+            .iter()
+            .find(|function| function.name == name)
+            .map(|function| function.address)
+    }
+
+    /// Formats `address` as `function+offset (file:line)`, or as a plain address if it is not in
+    /// a function.
+    pub fn describe(&self, address: u32) -> String {
+        let (name, offset) = match self.function(address) {
+            Some(function) => function,
+            None => return format!("{:#010x}", address),
+        };
+        match self.location(address) {
+            Some((file, line)) => format!("{}+{:#x} ({}:{})", name, offset, file.display(), line),
+            None => format!("{}+{:#x}", name, offset),
+        }
+    }
+
+    /// Appends the description of every hex number in `text` which is the address of a function,
+    /// e.g. `pc=0x08000404` becomes `pc=0x08000404 <main+0x4 (src/main.rs:12)>`.
+    pub fn annotate(&self, text: &str) -> String {
+        let is_word = |c: Option<char>| c.is_some_and(|c| c.is_ascii_alphanumeric() || c == '_');
+        let mut annotated = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find("0x") {
+            let word_start = !is_word(rest[..start].chars().next_back());
+            let digits = rest[start + 2..]
+                .find(|c: char| !c.is_ascii_hexdigit())
+                .unwrap_or(rest.len() - start - 2);
+            let end = start + 2 + digits;
+            let word_end = !is_word(rest[end..].chars().next());
+
+            annotated.push_str(&rest[..end]);
+            if word_start && word_end && (1..=8).contains(&digits) {
+                let address = u32::from_str_radix(&rest[start + 2..end], 16).unwrap_or_default();
+                if self.function(address).is_some() {
+                    annotated.push_str(&format!(" <{}>", self.describe(address)));
+                }
+            }
+            rest = &rest[end..];
+        }
+        annotated.push_str(rest);
+        annotated
+    }
+
+    pub fn debug_frame(&self) -> Option<&[u8]> {
+        self.debug_frame.as_deref()
+    }
+
+    #[cfg(test)]
+    pub fn with_debug_frame(debug_frame: Vec<u8>) -> Self {
+        Self {
+            debug_frame: Some(debug_frame),
+            ..Self::default()
+        }
+    }
+}
+
+/// Returns the contents of the section called `name`.
+fn section<'a>(elf: &Elf, data: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    elf.section_headers
+        .iter()
+        .find(|header| elf.shdr_strtab.get_at(header.sh_name) == Some(name))
+        .and_then(|header| header.file_range())
+        .and_then(|range| data.get(range))
+}
+
+/// Reads the line tables of all compilation units, sorted by address.
+fn parse_lines(elf: &Elf, data: &[u8]) -> Result<(Vec<LineRow>, Vec<PathBuf>)> {
+    let dwarf = gimli::Dwarf::load(|id| -> Result<Reader, gimli::Error> {
+        let data = section(elf, data, id.name()).unwrap_or_default();
+        Ok(EndianSlice::new(data, LittleEndian))
+    })?;
+    let current_dir = env::current_dir().unwrap_or_default();
+
+    let mut lines = Vec::new();
+    let mut files = Vec::new();
+    let mut file_indices = HashMap::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let program = match unit.line_program.clone() {
+            Some(program) => program,
+            None => continue,
+        };
+        let mut rows = program.rows();
+        while let Some((header, row)) = rows.next_row()? {
+            let address = row.address() as u32;
+            let (file, line) = match (row.end_sequence(), row.file(header), row.line()) {
+                (false, Some(file), Some(line)) => (file, line.get() as u32),
+                _ => {
+                    lines.push(LineRow {
+                        address,
+                        file: None,
+                        line: 0,
+                    });
+                    continue;
+                }
+            };
+            let path = file_path(&dwarf, &unit, header, file)?;
+            let path = path.strip_prefix(&current_dir).unwrap_or(&path).to_owned();
+            let file = *file_indices.entry(path.clone()).or_insert_with(|| {
+                files.push(path);
+                files.len() - 1
+            });
+            lines.push(LineRow {
+                address,
+                file: Some(file),
+                line,
+            });
+        }
+    }
+    // The end of a sequence comes before a sequence starting at the same address.
+    lines.sort_by_key(|row| (row.address, row.file.is_some()));
+
+    Ok((lines, files))
+}
+
+fn file_path(
+    dwarf: &gimli::Dwarf<Reader>,
+    unit: &Unit<Reader>,
+    header: &LineProgramHeader<Reader>,
+    file: &FileEntry<Reader>,
+) -> Result<PathBuf> {
+    let mut path = PathBuf::new();
+    if let Some(directory) = &unit.comp_dir {
+        path.push(&*directory.to_string_lossy());
+    }
+    if let Some(directory) = file.directory(header) {
+        path.push(&*dwarf.attr_string(unit, directory)?.to_string_lossy());
+    }
+    path.push(&*dwarf.attr_string(unit, file.path_name())?.to_string_lossy());
+    Ok(path)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::{Function, LineRow, Symbols};
+
+    #[test]
+    fn function_lookup() {
+        let function = |address, size, name: &str| Function {
+            address,
+            size,
+            name: name.to_owned(),
+        };
+        let symbols = Symbols {
+            functions: vec![
+                function(0x100, 0x20, "main"),
+                function(0x200, 0, "HardFault"),
+                function(0x300, 0x10, "app::run"),
+                function(0x400, 0, "__pre_init"),
+            ],
+            lines: Vec::new(),
+            files: Vec::new(),
+            debug_frame: None,
+        };
+
+        assert_eq!(symbols.function(0x110), Some(("main", 0x10)));
+        assert_eq!(symbols.function(0x120), None);
+        assert_eq!(symbols.function(0x2f0), Some(("HardFault", 0xf0)));
+        assert_eq!(symbols.function(0x80), None);
+        // The last function without a size doesn't cover the addresses after it.
+        assert_eq!(symbols.function(0x400), None);
+        assert_eq!(symbols.function(0x2000_1000), None);
+        assert_eq!(symbols.describe(0x304), "app::run+0x4");
+        assert_eq!(symbols.describe(0x400), "0x00000400");
+        assert_eq!(symbols.address_of("HardFault"), Some(0x200));
+    }
+
+    #[test]
+    fn annotate_addresses() {
+        let row = |address, file, line| LineRow {
+            address,
+            file,
+            line,
+        };
+        let symbols = Symbols {
+            functions: vec![Function {
+                address: 0x0800_0400,
+                size: 0x40,
+                name: "app::main".to_owned(),
+            }],
+            lines: vec![
+                row(0x0800_0400, Some(0), 10),
+                row(0x0800_0410, Some(0), 12),
+                row(0x0800_0440, None, 0),
+            ],
+            files: vec![PathBuf::from("src/main.rs")],
+            debug_frame: None,
+        };
+
+        assert_eq!(
+            symbols.annotate("pc=0x08000414 lr=0x0800041b sp=0x20001000"),
+            "pc=0x08000414 <app::main+0x14 (src/main.rs:12)> \
+             lr=0x0800041b <app::main+0x1b (src/main.rs:12)> sp=0x20001000"
+        );
+        // Only whole hex numbers are annotated.
+        assert_eq!(
+            symbols.annotate("x0x08000400 0x0800040000 0x08000400g"),
+            "x0x08000400 0x0800040000 0x08000400g"
+        );
+        assert_eq!(
+            symbols.annotate("0x08000400"),
+            "0x08000400 <app::main+0x0 (src/main.rs:10)>"
+        );
+    }
+}